@@ -44,6 +44,7 @@ fn find_library<'a>(names: &[&'a str]) -> (&'a str, Option<Library>) {
 }
 
 fn main() {
+    if env::var_os("CARGO_FEATURE_NCURSES").is_none() { return; }
     let ncurses_lib = if cfg!(target_os = "macos") {
         find_library(&["ncurses5", "ncurses"])
     } else {
@@ -57,6 +58,23 @@ fn main() {
     generate_int_type_rs(false, "attr_t", "attr_t", b"#include <ncurses.h>
 ", &[&ncurses_lib]);
     generate_int_const_rs("c_uint", "KEY_CODE_YES", "d", b"#include <ncurses.h>
+", &[&ncurses_lib]);
+    generate_int_type_rs(false, "mmask_t", "mmask_t", b"#include <ncurses.h>
+", &[&ncurses_lib]);
+    generate_int_consts_rs("mmask_t", "mouse", &[
+        "ALL_MOUSE_EVENTS", "REPORT_MOUSE_POSITION", "BUTTON_SHIFT", "BUTTON_CTRL", "BUTTON_ALT",
+        "BUTTON1_PRESSED", "BUTTON1_RELEASED", "BUTTON2_PRESSED", "BUTTON2_RELEASED",
+        "BUTTON3_PRESSED", "BUTTON3_RELEASED", "BUTTON4_PRESSED", "BUTTON5_PRESSED"
+    ], b"#include <ncurses.h>
+#ifndef BUTTON5_PRESSED
+#define BUTTON5_PRESSED 0
+#endif
+", &[&ncurses_lib]);
+    generate_opaque_type_rs("cchar_t", "cchar_t", b"#define _XOPEN_SOURCE_EXTENDED 1
+#include <ncurses.h>
+", &[&ncurses_lib]);
+    generate_int_const_rs("usize", "CCHARW_MAX", "d", b"#define _XOPEN_SOURCE_EXTENDED 1
+#include <ncurses.h>
 ", &[&ncurses_lib]);
 }
 
@@ -85,6 +103,28 @@ type ", rs_type_name.as_bytes(), b" = ", if is_signed { b"i" } else { b"u" }, &s
 " ]);
 }
 
+fn generate_opaque_type_rs(rs_type_name: &str, type_name: &str, includes: &[u8], libs: &[&(&str, Option<Library>)]) {
+    let layout = from_c_code(type_name, &[ includes, b"#include <stdio.h>
+#include <stddef.h>
+#include <limits.h>
+
+struct align_probe { char c; ", &type_name.as_bytes(), b" t; };
+
+int main(void) {
+    size_t align = offsetof(struct align_probe, t);
+    printf(\"[u%zu; %zu]\", align * CHAR_BIT, sizeof(", &type_name.as_bytes(), b") / align);
+    return 0;
+}
+" ], libs);
+    generate_rs(rs_type_name, &[ b"#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ", rs_type_name.as_bytes(), b" {
+    _data: ", &layout, b",
+}
+" ]);
+}
+
 fn generate_int_const_rs(type_name: &str, const_name: &str, printf: &str, includes: &[u8], libs: &[&(&str, Option<Library>)]) {
     let value = from_c_code(const_name, &[ includes, b"#include <stdio.h>
 
@@ -97,6 +137,21 @@ int main(void) {
 " ]);
 }
 
+fn generate_int_consts_rs(type_name: &str, name: &str, const_names: &[&str], includes: &[u8], libs: &[&(&str, Option<Library>)]) {
+    let mut printfs = Vec::new();
+    for const_name in const_names {
+        write!(printfs, "    printf(\"const {}: {} = %llu;\\n\", (unsigned long long)({}));
+", const_name, type_name, const_name).unwrap();
+    }
+    let code = from_c_code(name, &[ includes, b"#include <stdio.h>
+
+int main(void) {
+", &printfs, b"    return 0;
+}
+" ], libs);
+    generate_rs(name, &[ &code ]);
+}
+
 fn generate_rs(name: &str, code: &[&[u8]]) {
     let out_dir = env::var("OUT_DIR").expect("cannot get OUT_DIR");
     let rs = Path::new(&out_dir).join(format!("{}.rs", name));
@@ -0,0 +1,902 @@
+#![deny(warnings)]
+use std::io::{ self, Write };
+use std::mem::zeroed;
+use std::os::raw::{ c_int, c_void };
+use std::ptr::{ null, null_mut };
+use std::str::from_utf8;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicI32, Ordering };
+use std::thread::{ self, sleep };
+use std::time::{ Duration, Instant };
+use libc::{ termios, tcgetattr, tcsetattr, cfmakeraw, TCSAFLUSH, ISIG };
+use libc::{ winsize, ioctl, TIOCGWINSZ, poll, pollfd, POLLIN, read, write, EINTR, EAGAIN };
+use libc::{ sigaction, sigemptyset, sighandler_t, signal, raise, SIG_DFL, SA_RESTART, STDIN_FILENO, STDOUT_FILENO, EBUSY };
+use libc::{ SIGWINCH, SIGTSTP, SIGINT, SIGTERM, SIGHUP };
+
+use scr::{ Attr, Color, Scr, Texel, Key, Event, Mods, MouseAction, MouseButton, MouseEvent, Error, CursorShape };
+use sgr::{ acs, write_sgr, write_title, write_clipboard, decode_base64, term_colors };
+use dispatch::{ timeout_ms, Pipe };
+
+const ESC_DELAY: c_int = 25;
+const CLIPBOARD_TIMEOUT_MS: u64 = 200;
+const FLASH_MS: u64 = 100;
+
+// Write ends of the signal pipes of the open terminals: all the handler touches.
+static SIGNAL_FDS: [AtomicI32; 8] = [
+    AtomicI32::new(-1), AtomicI32::new(-1), AtomicI32::new(-1), AtomicI32::new(-1),
+    AtomicI32::new(-1), AtomicI32::new(-1), AtomicI32::new(-1), AtomicI32::new(-1),
+];
+
+// Signals ending the process: the terminal is restored before they are raised again.
+const FATAL_SIGNALS: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+lazy_static! {
+    // The actions replaced while any terminal is open.
+    static ref SAVED_ACTIONS: Mutex<Vec<(c_int, sigaction)>> = Mutex::new(Vec::new());
+}
+
+// Tells every open terminal which signal came; each reads its own pipe in `poll`.
+extern "C" fn on_signal(signal: c_int) {
+    let b = signal as u8;
+    for fd in SIGNAL_FDS.iter() {
+        let fd = fd.load(Ordering::SeqCst);
+        if fd >= 0 { unsafe { write(fd, &b as *const u8 as *const c_void, 1) }; }
+    }
+}
+
+fn register_signals(fd: c_int) -> Result<usize, Error> {
+    fn handle(signal: c_int, saved: &mut Vec<(c_int, sigaction)>) {
+        let mut old: sigaction = unsafe { zeroed() };
+        let mut action: sigaction = unsafe { zeroed() };
+        action.sa_sigaction = on_signal as extern "C" fn(c_int) as sighandler_t;
+        action.sa_flags = SA_RESTART;
+        unsafe { sigemptyset(&mut action.sa_mask) };
+        unsafe { sigaction(signal, &action, &mut old) };
+        saved.push((signal, old));
+    }
+    let mut saved = SAVED_ACTIONS.lock().unwrap();
+    let slot = SIGNAL_FDS.iter().position(|f| f.load(Ordering::SeqCst) < 0).ok_or(Error::Os { call: "sigaction", errno: EBUSY })?;
+    SIGNAL_FDS[slot].store(fd, Ordering::SeqCst);
+    if saved.is_empty() {
+        handle(SIGWINCH, &mut saved);
+        handle(SIGTSTP, &mut saved);
+        for &signal in FATAL_SIGNALS.iter() {
+            // As curses does, leave alone a signal the application ignores or handles itself.
+            let mut old: sigaction = unsafe { zeroed() };
+            unsafe { sigaction(signal, null(), &mut old) };
+            if old.sa_sigaction == SIG_DFL { handle(signal, &mut saved); }
+        }
+    }
+    Ok(slot)
+}
+
+fn unregister_signals(slot: usize) {
+    let mut saved = SAVED_ACTIONS.lock().unwrap();
+    SIGNAL_FDS[slot].store(-1, Ordering::SeqCst);
+    if SIGNAL_FDS.iter().any(|f| f.load(Ordering::SeqCst) >= 0) { return; }
+    for (signal, action) in saved.drain(..) {
+        unsafe { sigaction(signal, &action, null_mut()) };
+    }
+}
+
+fn errno() -> c_int {
+    io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+fn os_error(call: &'static str) -> Error {
+    Error::Os { call: call, errno: errno() }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Parsed {
+    Event(Event, usize),
+    Clipboard(String, usize),
+    Skip(usize),
+    Incomplete,
+    // Not UTF-8: the length of the bad sequence.
+    Invalid(usize),
+}
+
+fn csi_key(params: &[u8], final_: u8) -> Option<Event> {
+    let params = from_utf8(params).unwrap_or("");
+    let mut params = params.split(';').map(|p| p.parse::<u8>().ok());
+    let n = params.next().and_then(|n| n);
+    let mods = match params.next() {
+        Some(Some(m)) if m > 0 => Mods::from_bits_truncate(m - 1),
+        _ => Mods::NONE
+    };
+    let key = match final_ {
+        b'A' => Key::UP,
+        b'B' => Key::DOWN,
+        b'C' => Key::RIGHT,
+        b'D' => Key::LEFT,
+        b'E' => Key::B2,
+        b'F' => Key::END,
+        b'H' => Key::HOME,
+        b'P' => Key::f(1),
+        b'Q' => Key::f(2),
+        b'R' => Key::f(3),
+        b'S' => Key::f(4),
+        b'Z' => return Some(Event::Char('\t', Mods::SHIFT | mods)),
+        b'~' => match n {
+            Some(1) | Some(7) => Key::HOME,
+            Some(2) => Key::IC,
+            Some(3) => Key::DC,
+            Some(4) | Some(8) => Key::END,
+            Some(5) => Key::PPAGE,
+            Some(6) => Key::NPAGE,
+            Some(n @ 11 ..= 15) => Key::f(n - 10),
+            Some(n @ 17 ..= 21) => Key::f(n - 11),
+            Some(n @ 23 ..= 26) => Key::f(n - 12),
+            Some(n @ 28 ..= 29) => Key::f(n - 13),
+            Some(n @ 31 ..= 34) => Key::f(n - 14),
+            _ => return None
+        },
+        _ => return None
+    };
+    Some(Event::Key(key, mods))
+}
+
+fn mouse_event(cb: u32, y: isize, x: isize, release: bool) -> MouseEvent {
+    let mut mods = Mods::NONE;
+    if cb & 4 != 0 { mods |= Mods::SHIFT; }
+    if cb & 8 != 0 { mods |= Mods::ALT; }
+    if cb & 16 != 0 { mods |= Mods::CTRL; }
+    let button = match cb & 0xC3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        64 => Some(MouseButton::WheelUp),
+        65 => Some(MouseButton::WheelDown),
+        66 => Some(MouseButton::WheelLeft),
+        67 => Some(MouseButton::WheelRight),
+        _ => None
+    };
+    let action = if release || (cb & 0x23 == 3) {
+        MouseAction::Release
+    } else if cb & 32 != 0 {
+        if button.is_some() { MouseAction::Drag } else { MouseAction::Move }
+    } else {
+        MouseAction::Press
+    };
+    MouseEvent { y: y, x: x, button: button, action: action, mods: mods }
+}
+
+fn sgr_mouse(params: &[u8], final_: u8) -> Option<MouseEvent> {
+    let params = from_utf8(params).ok()?;
+    let mut params = params.split(';').map(|p| p.parse::<u32>().ok());
+    let cb = params.next()??;
+    let x = params.next()??;
+    let y = params.next()??;
+    if params.next().is_some() || x == 0 || y == 0 { return None; }
+    Some(mouse_event(cb, y as isize - 1, x as isize - 1, final_ == b'm'))
+}
+
+// The answer to a clipboard query: `52;<selections>;<base64>`.
+fn clipboard_reply(params: &[u8]) -> Option<String> {
+    let data = &params[3 ..];
+    let data = &data[data.iter().position(|&c| c == b';')? + 1 ..];
+    Some(String::from_utf8_lossy(&decode_base64(data)?).into_owned())
+}
+
+fn parse(b: &[u8]) -> Parsed {
+    if b.is_empty() { return Parsed::Incomplete; }
+    match b[0] {
+        0x1B => {
+            if b.len() < 2 { return Parsed::Incomplete; }
+            match b[1] {
+                b'[' => {
+                    let mut i = 2;
+                    while i < b.len() && b[i] >= 0x30 && b[i] <= 0x3F { i += 1; }
+                    let params_end = i;
+                    while i < b.len() && b[i] >= 0x20 && b[i] <= 0x2F { i += 1; }
+                    if i >= b.len() { return Parsed::Incomplete; }
+                    if b[i] == b'[' && params_end == 2 {
+                        if i + 1 >= b.len() { return Parsed::Incomplete; }
+                        return match b[i + 1] {
+                            c @ b'A' ..= b'E' => Parsed::Event(Event::Key(Key::f(c - b'A' + 1), Mods::NONE), i + 2),
+                            _ => Parsed::Skip(i + 2)
+                        };
+                    }
+                    if b[i] < 0x40 || b[i] > 0x7E { return Parsed::Skip(i); }
+                    if b[i] == b'M' && params_end == 2 {
+                        if i + 3 >= b.len() { return Parsed::Incomplete; }
+                        let cb = b[i + 1].wrapping_sub(32) as u32;
+                        let x = b[i + 2] as isize - 33;
+                        let y = b[i + 3] as isize - 33;
+                        return Parsed::Event(Event::Mouse(mouse_event(cb, y, x, false)), i + 4);
+                    }
+                    if params_end > 2 && b[2] == b'<' {
+                        return match sgr_mouse(&b[3 .. params_end], b[i]) {
+                            Some(e) if b[i] == b'M' || b[i] == b'm' => Parsed::Event(Event::Mouse(e), i + 1),
+                            _ => Parsed::Skip(i + 1)
+                        };
+                    }
+                    match csi_key(&b[2 .. params_end], b[i]) {
+                        Some(e) => Parsed::Event(e, i + 1),
+                        None => Parsed::Skip(i + 1)
+                    }
+                },
+                // A reply may arrive split across reads.
+                b']' if b.len() < 5 && b"52;".starts_with(&b[2 ..]) => Parsed::Incomplete,
+                b']' if b[2 ..].starts_with(b"52;") => {
+                    let mut i = 2;
+                    while i < b.len() && b[i] != 0x07 && b[i] != 0x1B { i += 1; }
+                    if i >= b.len() || (b[i] == 0x1B && i + 1 >= b.len()) { return Parsed::Incomplete; }
+                    let end = if b[i] == 0x07 { i + 1 } else if b[i + 1] == b'\\' { i + 2 } else { return Parsed::Skip(i); };
+                    match clipboard_reply(&b[2 .. i]) {
+                        Some(text) => Parsed::Clipboard(text, end),
+                        None => Parsed::Skip(end)
+                    }
+                },
+                b'O' => {
+                    if b.len() < 3 { return Parsed::Incomplete; }
+                    match b[2] {
+                        b'M' => Parsed::Event(Event::Key(Key::ENTER, Mods::NONE), 3),
+                        c => match csi_key(&[], c) {
+                            Some(e) => Parsed::Event(e, 3),
+                            None => Parsed::Skip(3)
+                        }
+                    }
+                },
+                _ => match parse(&b[1 ..]) {
+                    Parsed::Event(e, n) => Parsed::Event(e.with_mods(Mods::ALT), n + 1),
+                    Parsed::Skip(n) => Parsed::Skip(n + 1),
+                    Parsed::Clipboard(text, n) => Parsed::Clipboard(text, n + 1),
+                    Parsed::Invalid(n) => Parsed::Invalid(n + 1),
+                    Parsed::Incomplete => Parsed::Incomplete
+                }
+            }
+        },
+        0x7F => Parsed::Event(Event::Key(Key::BACKSPACE, Mods::NONE), 1),
+        b0 => {
+            let len = if b0 & 0x80 == 0 { 1 }
+                else if b0 & 0xE0 == 0xC0 { 2 }
+                else if b0 & 0xF0 == 0xE0 { 3 }
+                else if b0 & 0xF8 == 0xF0 { 4 }
+                else { return Parsed::Invalid(1); };
+            let tail = b[1 ..].iter().take(len - 1).take_while(|&&bi| bi & 0xC0 == 0x80).count();
+            if tail < len - 1 {
+                return if 1 + tail == b.len() { Parsed::Incomplete } else { Parsed::Invalid(1 + tail) };
+            }
+            match from_utf8(&b[.. len]).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Parsed::Event(Event::decode_char(c), len),
+                None => Parsed::Invalid(len)
+            }
+        }
+    }
+}
+
+pub struct Ansi {
+    fd_in: c_int,
+    fd_out: c_int,
+    saved_termios: termios,
+    signals: Arc<Pipe>,
+    signal_slot: Option<usize>,
+    suspend_pending: bool,
+    fatal_signal: Option<c_int>,
+    flash_until: Option<Instant>,
+    // Between `enter` and `leave`: only then is there a terminal state to restore.
+    entered: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    cursor: Option<(isize, isize)>,
+    cursor_is_visible: bool,
+    cursor_shape: CursorShape,
+    title: Option<String>,
+    style: Option<(Attr, Color, Option<Color>)>,
+    colors: u32,
+    esc_delay: c_int,
+    size: (isize, isize),
+}
+
+impl Ansi {
+    pub fn new() -> Result<Ansi, Error> {
+        Ansi::open(None, STDIN_FILENO, STDOUT_FILENO)
+    }
+    // Drives the terminal on the given descriptors, which stay open after drop.
+    // `term` defaults to $TERM.
+    pub fn open(term: Option<&str>, fd_in: c_int, fd_out: c_int) -> Result<Ansi, Error> {
+        let mut saved_termios: termios = unsafe { zeroed() };
+        if unsafe { tcgetattr(fd_in, &mut saved_termios) } != 0 { return Err(os_error("tcgetattr")); }
+        let mut ansi = Ansi {
+            fd_in: fd_in,
+            fd_out: fd_out,
+            saved_termios: saved_termios,
+            signals: Arc::new(Pipe::new()?),
+            signal_slot: None,
+            suspend_pending: false,
+            fatal_signal: None,
+            flash_until: None,
+            entered: false,
+            input: Vec::new(),
+            output: Vec::new(),
+            cursor: None,
+            cursor_is_visible: false,
+            cursor_shape: CursorShape::Default,
+            title: None,
+            style: None,
+            colors: term_colors(term),
+            esc_delay: ESC_DELAY,
+            size: (0, 0),
+        };
+        ansi.signal_slot = Some(register_signals(ansi.signals.wr)?);
+        ansi.size = ansi.size()?;
+        ansi.enter()?;
+        Ok(ansi)
+    }
+    fn enter(&mut self) -> Result<(), Error> {
+        if self.entered { return Ok(()); }
+        let mut raw = self.saved_termios;
+        unsafe { cfmakeraw(&mut raw) };
+        raw.c_lflag |= ISIG;
+        if unsafe { tcsetattr(self.fd_in, TCSAFLUSH, &raw) } != 0 { return Err(os_error("tcsetattr")); }
+        self.entered = true;
+        self.cursor = None;
+        self.cursor_is_visible = false;
+        self.style = None;
+        self.output.extend_from_slice(b"\x1b[?1049h\x1b[?7l\x1b[?25l\x1b[?1002h\x1b[?1006h\x1b[0m\x1b[2J");
+        if self.cursor_shape != CursorShape::Default {
+            write!(self.output, "\x1b[{} q", self.cursor_shape as u8).unwrap();
+        }
+        if let Some(ref title) = self.title {
+            self.output.extend_from_slice(b"\x1b[22;0t");
+            write_title(&mut self.output, title);
+        }
+        self.flush()
+    }
+    fn leave(&mut self) -> Result<(), Error> {
+        if !self.entered { return Ok(()); }
+        self.entered = false;
+        if self.flash_until.take().is_some() {
+            self.output.extend_from_slice(b"\x1b[?5l");
+        }
+        if self.cursor_shape != CursorShape::Default {
+            self.output.extend_from_slice(b"\x1b[0 q");
+        }
+        if self.title.is_some() {
+            self.output.extend_from_slice(b"\x1b[23;0t");
+        }
+        self.output.extend_from_slice(b"\x1b[0m\x1b[?1006l\x1b[?1002l\x1b[?7h\x1b[?25h\x1b[?1049l");
+        let flushed = self.flush();
+        if unsafe { tcsetattr(self.fd_in, TCSAFLUSH, &self.saved_termios) } != 0 { return Err(os_error("tcsetattr")); }
+        flushed
+    }
+    fn size(&self) -> Result<(isize, isize), Error> {
+        let mut ws: winsize = unsafe { zeroed() };
+        if unsafe { ioctl(self.fd_out, TIOCGWINSZ, &mut ws) } != 0 { return Err(os_error("ioctl(TIOCGWINSZ)")); }
+        Ok((ws.ws_row as isize, ws.ws_col as isize))
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        let mut written = 0;
+        while written < self.output.len() {
+            let rest = &self.output[written ..];
+            let n = unsafe { write(self.fd_out, rest.as_ptr() as *const c_void, rest.len()) };
+            if n < 0 {
+                let e = errno();
+                if e == EINTR || e == EAGAIN { continue; }
+                self.output.clear();
+                return Err(Error::Os { call: "write", errno: e });
+            }
+            written += n as usize;
+        }
+        self.output.clear();
+        Ok(())
+    }
+    fn end_flash(&mut self) -> Result<(), Error> {
+        match self.flash_until {
+            Some(until) if Instant::now() >= until => {
+                self.flash_until = None;
+                self.output.extend_from_slice(b"\x1b[?5l");
+                self.flush()
+            },
+            _ => Ok(())
+        }
+    }
+    fn read_signals(&mut self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe { read(self.signals.rd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            if n <= 0 { break; }
+            // SIGWINCH needs no note: the size is compared on each pass anyway.
+            for &b in &buf[.. n as usize] {
+                if b == SIGTSTP as u8 { self.suspend_pending = true; }
+                if FATAL_SIGNALS.contains(&(b as c_int)) { self.fatal_signal = Some(b as c_int); }
+            }
+        }
+    }
+    // Also returns true, having read nothing, when a signal came in.
+    fn wait_input(&mut self, timeout: c_int) -> Result<bool, Error> {
+        let mut fds = [
+            pollfd { fd: self.fd_in, events: POLLIN, revents: 0 },
+            pollfd { fd: self.signals.rd, events: POLLIN, revents: 0 },
+        ];
+        let n = unsafe { poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+        if n < 0 {
+            let e = errno();
+            return if e == EINTR { Ok(true) } else { Err(Error::Os { call: "poll", errno: e }) };
+        }
+        if n == 0 { return Ok(false); }
+        if fds[0].revents == 0 { return Ok(true); }
+        let mut buf = [0u8; 256];
+        let n = unsafe { read(self.fd_in, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        if n < 0 {
+            let e = errno();
+            return if e == EINTR || e == EAGAIN { Ok(true) } else { Err(Error::Os { call: "read", errno: e }) };
+        }
+        if n == 0 { return Err(Error::InputClosed); }
+        self.input.extend_from_slice(&buf[.. n as usize]);
+        Ok(true)
+    }
+}
+
+impl Scr for Ansi {
+    fn get_height(&self) -> Result<isize, Error> {
+        Ok(self.size()?.0)
+    }
+    fn get_width(&self) -> Result<isize, Error> {
+        Ok(self.size()?.1)
+    }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
+        if y < 0 || x < 0 { return Err(Error::OutOfBounds { y: y, x: x }); }
+        if c.is_tail() { return Ok(()); }
+        if self.cursor != Some((y, x)) {
+            write!(self.output, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+        }
+        let attr = if self.colors < 16 && c.fg.is_bright() { c.attr | Attr::BOLD } else { c.attr };
+        let style = (attr, c.fg.approx(self.colors), c.bg.map(|bg| bg.approx(self.colors)));
+        if self.style != Some(style) {
+            write_sgr(&mut self.output, style.0, style.1, style.2);
+            self.style = Some(style);
+        }
+        if c.attr.contains(Attr::ALTCHARSET) {
+            let mut b = [0; 4];
+            self.output.extend_from_slice(acs(c.ch.base()).encode_utf8(&mut b).as_bytes());
+        } else {
+            write!(self.output, "{}", c.ch).unwrap();
+        }
+        self.cursor = Some((y, x + c.width()));
+        Ok(())
+    }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
+        self.end_flash()?;
+        match cursor {
+            None => {
+                if self.cursor_is_visible {
+                    self.output.extend_from_slice(b"\x1b[?25l");
+                    self.cursor_is_visible = false;
+                }
+            },
+            Some((y, x)) => {
+                write!(self.output, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+                self.cursor = Some((y, x));
+                if !self.cursor_is_visible {
+                    self.output.extend_from_slice(b"\x1b[?25h");
+                    self.cursor_is_visible = true;
+                }
+            }
+        }
+        self.flush()
+    }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> {
+        if shape != self.cursor_shape {
+            write!(self.output, "\x1b[{} q", shape as u8).unwrap();
+            self.cursor_shape = shape;
+        }
+        Ok(())
+    }
+    // The title stack of xterm keeps the original title.
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        if self.title.is_none() {
+            self.output.extend_from_slice(b"\x1b[22;0t");
+        }
+        write_title(&mut self.output, title);
+        self.title = Some(title.to_string());
+        self.flush()
+    }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> {
+        write_clipboard(&mut self.output, text);
+        self.flush()
+    }
+    // Keeps the input arriving before the answer; terminals not allowing the query never answer.
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> {
+        self.output.extend_from_slice(b"\x1b]52;c;?\x07");
+        self.flush()?;
+        let deadline = Instant::now() + Duration::from_millis(CLIPBOARD_TIMEOUT_MS);
+        loop {
+            let mut i = 0;
+            while i < self.input.len() {
+                match parse(&self.input[i ..]) {
+                    Parsed::Clipboard(text, n) => {
+                        self.input.drain(i .. i + n);
+                        return Ok(Some(text));
+                    },
+                    Parsed::Event(_, n) | Parsed::Skip(n) | Parsed::Invalid(n) => i += n,
+                    Parsed::Incomplete => break
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline { return Ok(None); }
+            self.wait_input(timeout_ms(Some(deadline - now)))?;
+        }
+    }
+    fn beep(&mut self) -> Result<(), Error> {
+        self.output.push(0x07);
+        self.flush()
+    }
+    // Reverse video for a moment, as the `flash` of xterm terminfo does. Rather than sleep, it
+    // wakes `poll` through the signal pipe, and the next `poll` or `refresh` turns it back.
+    fn flash(&mut self) -> Result<(), Error> {
+        if self.flash_until.is_some() { return Ok(()); }
+        self.output.extend_from_slice(b"\x1b[?5h");
+        self.flush()?;
+        self.flash_until = Some(Instant::now() + Duration::from_millis(FLASH_MS));
+        let signals = Arc::clone(&self.signals);
+        thread::spawn(move || {
+            sleep(Duration::from_millis(FLASH_MS));
+            signals.wake();
+        });
+        Ok(())
+    }
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            self.read_signals();
+            if let Some(fatal) = self.fatal_signal.take() {
+                self.leave()?;
+                unsafe { signal(fatal, SIG_DFL) };
+                unsafe { raise(fatal) };
+                return Err(Error::InputClosed);
+            }
+            self.end_flash()?;
+            if self.suspend_pending {
+                self.suspend_pending = false;
+                return Ok(Some(Event::Key(Key::SUSPEND, Mods::NONE)));
+            }
+            let size = self.size()?;
+            if size != self.size {
+                self.size = size;
+                return Ok(Some(Event::Key(Key::RESIZE, Mods::NONE)));
+            }
+            let timeout = match parse(&self.input) {
+                Parsed::Event(e, n) => {
+                    self.input.drain(.. n);
+                    return Ok(Some(e));
+                },
+                Parsed::Skip(n) | Parsed::Clipboard(_, n) => {
+                    self.input.drain(.. n);
+                    continue;
+                },
+                // Only the bad bytes go; the input after them is still there for the next call.
+                Parsed::Invalid(n) => {
+                    let bytes = self.input.drain(.. n).collect();
+                    return Err(Error::InvalidUtf8(bytes));
+                },
+                Parsed::Incomplete if !self.input.is_empty() => self.esc_delay,
+                Parsed::Incomplete => timeout_ms(deadline.map(|d| {
+                    let now = Instant::now();
+                    if d > now { d - now } else { Duration::from_millis(0) }
+                }))
+            };
+            if !self.wait_input(timeout)? {
+                if self.input.is_empty() { return Ok(None); }
+                if self.input[0] != 0x1B {
+                    let bytes = self.input.drain(..).collect();
+                    return Err(Error::InvalidUtf8(bytes));
+                }
+                // Nothing completed the sequence: e.g. Alt-] looks like the start of a reply.
+                if let Parsed::Event(e, n) = parse(&self.input[1 ..]) {
+                    self.input.drain(.. n + 1);
+                    return Ok(Some(e.with_mods(Mods::ALT)));
+                }
+                self.input.remove(0);
+                return Ok(Some(Event::Char('\x1B', Mods::NONE)));
+            }
+        }
+    }
+    fn set_esc_delay(&mut self, ms: u32) {
+        self.esc_delay = ms as c_int;
+    }
+    fn input_fd(&self) -> Option<c_int> {
+        Some(self.fd_in)
+    }
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> {
+        Some((self.fd_in, self.fd_out))
+    }
+    fn signal_fd(&self) -> Option<c_int> {
+        Some(self.signals.rd)
+    }
+    fn suspend(&mut self) -> Result<(), Error> {
+        self.leave()
+    }
+    fn resume(&mut self) -> Result<(), Error> {
+        self.enter()
+    }
+}
+
+impl Drop for Ansi {
+    fn drop(&mut self) {
+        self.leave().ok();
+        if let Some(slot) = self.signal_slot { unregister_signals(slot); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ffi::{ CStr, CString };
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ Command, Stdio };
+    use std::os::raw::{ c_int, c_void };
+    use std::thread;
+    use std::time::{ Duration, Instant };
+    use libc::{ posix_openpt, grantpt, unlockpt, ptsname, open, close, read, write, poll, pollfd, POLLIN, O_RDWR, O_NOCTTY };
+    use libc::{ winsize, ioctl, TIOCSWINSZ, raise, SIGWINCH, SIGINT };
+    use ansi::{ Ansi, parse, Parsed };
+    use dispatch::Dispatcher;
+    use scr::{ Scr, Attr, Color, Texel, Key, Event, Mods, MouseAction, MouseButton, MouseEvent, CursorShape, Error };
+    use sgr::write_input;
+
+    fn read_available(fd: c_int) -> String {
+        let mut output = Vec::new();
+        let mut fds = pollfd { fd: fd, events: POLLIN, revents: 0 };
+        while unsafe { poll(&mut fds, 1, 100) } > 0 {
+            let mut buf = [0u8; 256];
+            let n = unsafe { read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+            if n <= 0 { break; }
+            output.extend_from_slice(&buf[.. n as usize]);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    // Returns the master and slave ends of a new 3x10 pty.
+    fn open_pty() -> (c_int, c_int) {
+        let master = unsafe { posix_openpt(O_RDWR | O_NOCTTY) };
+        assert!(master >= 0);
+        assert_eq!(0, unsafe { grantpt(master) });
+        assert_eq!(0, unsafe { unlockpt(master) });
+        let name = unsafe { CStr::from_ptr(ptsname(master)) }.to_owned();
+        let slave = unsafe { open(name.as_ptr(), O_RDWR | O_NOCTTY) };
+        assert!(slave >= 0);
+        let ws = winsize { ws_row: 3, ws_col: 10, ws_xpixel: 0, ws_ypixel: 0 };
+        assert_eq!(0, unsafe { ioctl(master, TIOCSWINSZ, &ws) });
+        (master, slave)
+    }
+
+    #[test]
+    fn render_to_pty() {
+        let (master, slave) = open_pty();
+        {
+            let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+            assert_eq!((Ok(3), Ok(10)), (scr.get_height(), scr.get_width()));
+            scr.out(1, 2, &Texel { ch: 'Z'.into(), attr: Attr::NORMAL, fg: Color::Indexed(200), bg: None }).unwrap();
+            scr.refresh(None).unwrap();
+            assert!(read_available(master).ends_with("\x1b[2;3H\x1b[0;38;5;200;49mZ"));
+            unsafe { write(master, b"q".as_ptr() as *const c_void, 1) };
+            assert_eq!(Ok(Some(Event::Char('q', Mods::NONE))), scr.poll(Some(Duration::from_secs(1))));
+            let ws = winsize { ws_row: 4, ws_col: 12, ws_xpixel: 0, ws_ypixel: 0 };
+            assert_eq!(0, unsafe { ioctl(master, TIOCSWINSZ, &ws) });
+            assert_eq!(Ok(Some(Event::Key(Key::RESIZE, Mods::NONE))), scr.poll(Some(Duration::from_millis(0))));
+            assert_eq!(Ok(12), scr.get_width());
+            scr.set_cursor_shape(CursorShape::Bar).unwrap();
+            scr.refresh(Some((0, 1))).unwrap();
+            assert!(read_available(master).ends_with("\x1b[6 q\x1b[1;2H\x1b[?25h"));
+            scr.set_title("a").unwrap();
+            scr.set_title("b").unwrap();
+            assert_eq!("\x1b[22;0t\x1b]0;a\x07\x1b]0;b\x07", read_available(master));
+            let reply = b"k\x1b]52;c;YWI=\x07";
+            unsafe { write(master, reply.as_ptr() as *const c_void, reply.len()) };
+            assert_eq!(Ok(Some("ab".to_string())), scr.get_clipboard());
+            assert_eq!("\x1b]52;c;?\x07", read_available(master));
+            assert_eq!(Ok(Some(Event::Char('k', Mods::NONE))), scr.poll(Some(Duration::from_millis(0))));
+        }
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    #[test]
+    fn clipboard_reply_keeps_input_order() {
+        let (master, slave) = open_pty();
+        {
+            let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+            read_available(master);
+            let reply = b"ab\x1b[A\x1b]52;c;eHk=\x1b\\c";
+            unsafe { write(master, reply.as_ptr() as *const c_void, reply.len()) };
+            assert_eq!(Ok(Some("xy".to_string())), scr.get_clipboard());
+            let no_wait = Some(Duration::from_millis(0));
+            assert_eq!(Ok(Some(Event::Char('a', Mods::NONE))), scr.poll(no_wait));
+            assert_eq!(Ok(Some(Event::Char('b', Mods::NONE))), scr.poll(no_wait));
+            assert_eq!(Ok(Some(Event::Key(Key::UP, Mods::NONE))), scr.poll(no_wait));
+            assert_eq!(Ok(Some(Event::Char('c', Mods::NONE))), scr.poll(Some(Duration::from_secs(1))));
+            assert_eq!(Ok(None), scr.poll(no_wait));
+            unsafe { write(master, b"a\xFFb".as_ptr() as *const c_void, 3) };
+            assert_eq!(Ok(Some(Event::Char('a', Mods::NONE))), scr.poll(Some(Duration::from_secs(1))));
+            assert_eq!(Err(Error::InvalidUtf8(vec![0xFF])), scr.poll(no_wait));
+            assert_eq!(Ok(Some(Event::Char('b', Mods::NONE))), scr.poll(no_wait));
+            unsafe { write(master, b"\x1b]".as_ptr() as *const c_void, 2) };
+            assert_eq!(Ok(Some(Event::Char(']', Mods::ALT))), scr.poll(Some(Duration::from_secs(1))));
+        }
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    // Run by `interrupt_restores_terminal` in a child process, which the signal ends.
+    #[test]
+    fn interrupted_child() {
+        let name = match env::var("NTFL_TEST_PTY") { Ok(name) => CString::new(name).unwrap(), Err(_) => return };
+        let slave = unsafe { open(name.as_ptr(), O_RDWR | O_NOCTTY) };
+        let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+        unsafe { raise(SIGINT) };
+        scr.poll(Some(Duration::from_secs(5))).ok();
+        panic!("still alive");
+    }
+
+    #[test]
+    fn interrupt_restores_terminal() {
+        let (master, slave) = open_pty();
+        let name = unsafe { CStr::from_ptr(ptsname(master)) }.to_str().unwrap().to_string();
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(&["--exact", "ansi::tests::interrupted_child", "--nocapture"])
+            .env("NTFL_TEST_PTY", name)
+            .stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn().unwrap();
+        let status = child.wait().unwrap();
+        assert_eq!(Some(SIGINT), status.signal());
+        assert!(read_available(master).ends_with("\x1b[?25h\x1b[?1049l"));
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    #[test]
+    fn drop_while_suspended() {
+        let (master, slave) = open_pty();
+        {
+            let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+            scr.suspend().unwrap();
+            assert!(read_available(master).ends_with("\x1b[?1049l"));
+            scr.suspend().unwrap();
+        }
+        assert_eq!("", read_available(master));
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    #[test]
+    fn flash_without_blocking() {
+        let (master, slave) = open_pty();
+        {
+            let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+            read_available(master);
+            let start = Instant::now();
+            scr.flash().unwrap();
+            assert!(start.elapsed() < Duration::from_millis(50));
+            assert_eq!("\x1b[?5h", read_available(master));
+            assert_eq!(Ok(None), scr.poll(Some(Duration::from_millis(300))));
+            assert_eq!("\x1b[?5l", read_available(master));
+        }
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    #[test]
+    fn resize_signal_wakes_wait() {
+        let (master, slave) = open_pty();
+        {
+            let mut scr = Ansi::open(Some("xterm-256color"), slave, slave).unwrap();
+            let dispatcher = Dispatcher::<()>::new().unwrap();
+            // Raised on another thread, the signal cannot interrupt the wait: only the signal pipe wakes it.
+            let resizer = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                let ws = winsize { ws_row: 4, ws_col: 12, ws_xpixel: 0, ws_ypixel: 0 };
+                assert_eq!(0, unsafe { ioctl(master, TIOCSWINSZ, &ws) });
+                unsafe { raise(SIGWINCH) };
+            });
+            assert_eq!(Ok(Some(Event::Key(Key::RESIZE, Mods::NONE))), dispatcher.wait(&mut scr));
+            resizer.join().unwrap();
+            assert_eq!(Ok(12), scr.get_width());
+        }
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    #[test]
+    fn input_round_trip() {
+        let events = [
+            Event::Char('ы', Mods::NONE), Event::Char('\n', Mods::NONE), Event::Char('x', Mods::CTRL),
+            Event::Char('q', Mods::ALT), Event::Char('\t', Mods::SHIFT), Event::Key(Key::LEFT, Mods::CTRL),
+            Event::Key(Key::f(1), Mods::NONE), Event::Key(Key::f(9), Mods::ALT), Event::Key(Key::DC, Mods::NONE),
+            Event::Key(Key::BACKSPACE, Mods::NONE), Event::Key(Key::ENTER, Mods::NONE),
+            Event::Mouse(MouseEvent { y: 4, x: 200, button: Some(MouseButton::Right), action: MouseAction::Release, mods: Mods::SHIFT }),
+            Event::Mouse(MouseEvent { y: 0, x: 0, button: None, action: MouseAction::Move, mods: Mods::NONE }),
+        ];
+        for e in events.iter() {
+            let mut buf = Vec::new();
+            assert!(write_input(&mut buf, e));
+            assert_eq!(Parsed::Event(e.clone(), buf.len()), parse(&buf));
+        }
+    }
+
+    #[test]
+    fn parse_chars() {
+        assert_eq!(Parsed::Event(Event::Char('a', Mods::NONE), 1), parse(b"ab"));
+        assert_eq!(Parsed::Event(Event::Char('ы', Mods::NONE), 2), parse("ы".as_bytes()));
+        assert_eq!(Parsed::Event(Event::Char('\n', Mods::NONE), 1), parse(b"\r"));
+        assert_eq!(Parsed::Incomplete, parse(&"ы".as_bytes()[.. 1]));
+        assert_eq!(Parsed::Invalid(1), parse(b"\xFFa"));
+        assert_eq!(Parsed::Invalid(2), parse(b"\xE2\x82a"));
+        assert_eq!(Parsed::Invalid(2), parse(b"\xC0\x80"));
+        assert_eq!(Parsed::Invalid(2), parse(b"\x1b\xFFa"));
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(Parsed::Event(Event::Key(Key::UP, Mods::NONE), 3), parse(b"\x1b[A"));
+        assert_eq!(Parsed::Event(Event::Key(Key::LEFT, Mods::NONE), 3), parse(b"\x1bOD"));
+        assert_eq!(Parsed::Event(Event::Key(Key::DC, Mods::NONE), 4), parse(b"\x1b[3~"));
+        assert_eq!(Parsed::Event(Event::Key(Key::f(1), Mods::NONE), 3), parse(b"\x1bOP"));
+        assert_eq!(Parsed::Event(Event::Key(Key::f(12), Mods::NONE), 5), parse(b"\x1b[24~"));
+        assert_eq!(Parsed::Event(Event::Key(Key::f(5), Mods::NONE), 4), parse(b"\x1b[[E"));
+        assert_eq!(Parsed::Event(Event::Key(Key::BACKSPACE, Mods::NONE), 1), parse(b"\x7f"));
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        assert_eq!(Parsed::Event(Event::Key(Key::RIGHT, Mods::CTRL), 6), parse(b"\x1b[1;5C"));
+        assert_eq!(Parsed::Event(Event::Key(Key::DC, Mods::ALT), 6), parse(b"\x1b[3;3~"));
+        assert_eq!(Parsed::Event(Event::Key(Key::f(5), Mods::SHIFT | Mods::CTRL), 7), parse(b"\x1b[15;6~"));
+        assert_eq!(Parsed::Event(Event::Char('\t', Mods::SHIFT), 3), parse(b"\x1b[Z"));
+        assert_eq!(Parsed::Event(Event::Char('c', Mods::CTRL), 1), parse(b"\x03"));
+        assert_eq!(Parsed::Event(Event::Char('x', Mods::ALT), 2), parse(b"\x1bx"));
+        assert_eq!(Parsed::Event(Event::Char('ы', Mods::ALT), 3), parse("\x1bы".as_bytes()));
+        assert_eq!(Parsed::Event(Event::Key(Key::UP, Mods::ALT), 4), parse(b"\x1b\x1b[A"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b\x1b"));
+    }
+
+    #[test]
+    fn parse_mouse() {
+        assert_eq!(
+            Parsed::Event(Event::Mouse(MouseEvent { y: 4, x: 9, button: Some(MouseButton::Left), action: MouseAction::Press, mods: Mods::NONE }), 10),
+            parse(b"\x1b[<0;10;5M")
+        );
+        assert_eq!(
+            Parsed::Event(Event::Mouse(MouseEvent { y: 0, x: 0, button: Some(MouseButton::Right), action: MouseAction::Release, mods: Mods::CTRL }), 10),
+            parse(b"\x1b[<18;1;1m")
+        );
+        assert_eq!(
+            Parsed::Event(Event::Mouse(MouseEvent { y: 2, x: 3, button: Some(MouseButton::Left), action: MouseAction::Drag, mods: Mods::SHIFT }), 10),
+            parse(b"\x1b[<36;4;3M")
+        );
+        assert_eq!(
+            Parsed::Event(Event::Mouse(MouseEvent { y: 0, x: 1, button: Some(MouseButton::WheelDown), action: MouseAction::Press, mods: Mods::NONE }), 10),
+            parse(b"\x1b[<65;2;1M")
+        );
+        assert_eq!(
+            Parsed::Event(Event::Mouse(MouseEvent { y: 1, x: 0, button: None, action: MouseAction::Release, mods: Mods::NONE }), 6),
+            parse(b"\x1b[M#!\"")
+        );
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[M#"));
+    }
+
+    #[test]
+    fn parse_escape() {
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[1;5"));
+        assert_eq!(Parsed::Skip(5), parse(b"\x1b[?1uA"));
+    }
+
+    #[test]
+    fn parse_clipboard_reply() {
+        assert_eq!(Parsed::Clipboard("ab".to_string(), 12), parse(b"\x1b]52;c;YWI=\x07x"));
+        assert_eq!(Parsed::Clipboard("ы".to_string(), 13), parse(b"\x1b]52;p;0Ys=\x1b\\"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b]52;c;YW"));
+        assert_eq!(Parsed::Skip(9), parse(b"\x1b]52;c;!\x07"));
+        assert_eq!(Parsed::Event(Event::Char(']', Mods::ALT), 2), parse(b"\x1b]x"));
+        assert_eq!(Parsed::Clipboard("ab".to_string(), 13), parse(b"\x1b\x1b]52;c;YWI=\x07"));
+        for reply in [&b"\x1b]52;c;YWI=\x07"[..], &b"\x1b]52;c;YWI=\x1b\\"[..]].iter() {
+            for n in 1 .. reply.len() {
+                assert_eq!(Parsed::Incomplete, parse(&reply[.. n]), "{:?}", &reply[.. n]);
+            }
+            assert_eq!(Parsed::Clipboard("ab".to_string(), reply.len()), parse(reply));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+#![deny(warnings)]
+use std::io::{ self, Write };
+use std::os::raw::c_int;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+
+use scr::{ Attr, Color, Scr, Texel, Key, Event, Error, CursorShape };
+use sgr::{ acs, write_sgr, write_input, write_title };
+
+fn io_error(call: &'static str, e: io::Error) -> Error {
+    Error::Os { call: call, errno: e.raw_os_error().unwrap_or(0) }
+}
+
+fn write_json_str(buf: &mut Vec<u8>, s: &str) {
+    buf.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.extend_from_slice(b"\\\""),
+            '\\' => buf.extend_from_slice(b"\\\\"),
+            '\n' => buf.extend_from_slice(b"\\n"),
+            '\r' => buf.extend_from_slice(b"\\r"),
+            '\t' => buf.extend_from_slice(b"\\t"),
+            c if (c as u32) < 0x20 || c == '\x7F' => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => {
+                let mut b = [0; 4];
+                buf.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+            }
+        }
+    }
+    buf.push(b'"');
+}
+
+// Forwards to the wrapped screen and records its output and input as an asciicast v2 stream.
+pub struct Recorder<S : Scr, W : Write> {
+    scr: S,
+    cast: W,
+    start: Instant,
+    output: Vec<u8>,
+    cursor: Option<(isize, isize)>,
+    cursor_is_visible: bool,
+    cursor_shape: CursorShape,
+    style: Option<(Attr, Color, Option<Color>)>,
+}
+
+impl<S : Scr, W : Write> Recorder<S, W> {
+    pub fn new(scr: S, mut cast: W) -> Result<Recorder<S, W>, Error> {
+        let height = scr.get_height()?;
+        let width = scr.get_width()?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        writeln!(cast, "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}}}", width, height, timestamp)
+            .map_err(|e| io_error("write", e))?;
+        Ok(Recorder {
+            scr: scr,
+            cast: cast,
+            start: Instant::now(),
+            output: b"\x1b[?25l\x1b[H\x1b[2J".to_vec(),
+            cursor: None,
+            cursor_is_visible: false,
+            cursor_shape: CursorShape::Default,
+            style: None,
+        })
+    }
+    pub fn get_ref(&self) -> &S { &self.scr }
+    pub fn get_mut(&mut self) -> &mut S { &mut self.scr }
+    pub fn into_inner(self) -> (S, W) { (self.scr, self.cast) }
+    fn event(&mut self, code: &str, data: &str) -> Result<(), Error> {
+        let elapsed = self.start.elapsed();
+        let mut line = Vec::new();
+        write!(line, "[{}.{:06}, \"{}\", ", elapsed.as_secs(), elapsed.subsec_micros(), code).unwrap();
+        write_json_str(&mut line, data);
+        line.extend_from_slice(b"]\n");
+        self.cast.write_all(&line).and_then(|()| self.cast.flush()).map_err(|e| io_error("write", e))
+    }
+    fn record_input(&mut self, e: &Event) -> Result<(), Error> {
+        if let Event::Key(Key::RESIZE, _) = *e {
+            let size = format!("{}x{}", self.scr.get_width()?, self.scr.get_height()?);
+            self.event("r", &size)?;
+            self.output.extend_from_slice(b"\x1b[H\x1b[2J");
+            self.cursor = None;
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        if !write_input(&mut buf, e) { return Ok(()); }
+        self.event("i", &String::from_utf8_lossy(&buf))
+    }
+}
+
+impl<S : Scr, W : Write> Scr for Recorder<S, W> {
+    fn get_height(&self) -> Result<isize, Error> { self.scr.get_height() }
+    fn get_width(&self) -> Result<isize, Error> { self.scr.get_width() }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
+        self.scr.out(y, x, c)?;
+        if c.is_tail() { return Ok(()); }
+        if self.cursor != Some((y, x)) {
+            write!(self.output, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+        }
+        let style = (c.attr, c.fg, c.bg);
+        if self.style != Some(style) {
+            write_sgr(&mut self.output, style.0, style.1, style.2);
+            self.style = Some(style);
+        }
+        if c.attr.contains(Attr::ALTCHARSET) {
+            write!(self.output, "{}", acs(c.ch.base())).unwrap();
+        } else {
+            write!(self.output, "{}", c.ch).unwrap();
+        }
+        self.cursor = Some((y, x + c.width()));
+        Ok(())
+    }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
+        self.scr.refresh(cursor)?;
+        match cursor {
+            None => {
+                if self.cursor_is_visible {
+                    self.output.extend_from_slice(b"\x1b[?25l");
+                    self.cursor_is_visible = false;
+                }
+            },
+            Some((y, x)) => {
+                write!(self.output, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+                self.cursor = Some((y, x));
+                if !self.cursor_is_visible {
+                    self.output.extend_from_slice(b"\x1b[?25h");
+                    self.cursor_is_visible = true;
+                }
+            }
+        }
+        if self.output.is_empty() { return Ok(()); }
+        let output = String::from_utf8(self.output.split_off(0)).unwrap();
+        self.event("o", &output)
+    }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> {
+        self.scr.set_cursor_shape(shape)?;
+        if shape != self.cursor_shape {
+            write!(self.output, "\x1b[{} q", shape as u8).unwrap();
+            self.cursor_shape = shape;
+        }
+        Ok(())
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        self.scr.set_title(title)?;
+        write_title(&mut self.output, title);
+        Ok(())
+    }
+    fn beep(&mut self) -> Result<(), Error> {
+        self.scr.beep()?;
+        self.output.push(0x07);
+        Ok(())
+    }
+    // Recorded as the reverse video pair the ansi backend sends, without its pause.
+    fn flash(&mut self) -> Result<(), Error> {
+        self.scr.flash()?;
+        self.output.extend_from_slice(b"\x1b[?5h\x1b[?5l");
+        Ok(())
+    }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> { self.scr.set_clipboard(text) }
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> { self.scr.get_clipboard() }
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        let e = self.scr.poll(timeout)?;
+        if let Some(ref e) = e { self.record_input(e)?; }
+        Ok(e)
+    }
+    fn getch(&mut self) -> Result<Event, Error> {
+        let e = self.scr.getch()?;
+        self.record_input(&e)?;
+        Ok(e)
+    }
+    fn set_esc_delay(&mut self, ms: u32) { self.scr.set_esc_delay(ms) }
+    fn input_fd(&self) -> Option<c_int> { self.scr.input_fd() }
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> { self.scr.terminal_fds() }
+    fn signal_fd(&self) -> Option<c_int> { self.scr.signal_fd() }
+    fn input_timeout(&self) -> Option<Duration> { self.scr.input_timeout() }
+    fn suspend(&mut self) -> Result<(), Error> { self.scr.suspend() }
+    fn resume(&mut self) -> Result<(), Error> { self.scr.resume() }
+}
+
+#[cfg(test)]
+mod tests {
+    use cast::Recorder;
+    use mem::MemScr;
+    use scr::{ Scr, Attr, Color, Texel, Key, Event, Mods };
+
+    #[test]
+    fn records_frames_and_input() {
+        let mut m = MemScr::new(2, 3);
+        m.push_char('q');
+        m.push_key(Key::UP);
+        m.push_resize(4, 5);
+        let mut r = Recorder::new(m, Vec::new()).unwrap();
+        r.out(1, 1, &Texel { ch: '"'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }).unwrap();
+        r.refresh(None).unwrap();
+        r.refresh(None).unwrap();
+        assert_eq!(Ok(Event::Char('q', Mods::NONE)), r.getch());
+        assert_eq!(Ok(Event::Key(Key::UP, Mods::NONE)), r.getch());
+        assert_eq!(Ok(Some(Event::Key(Key::RESIZE, Mods::NONE))), r.poll(None));
+        r.refresh(Some((0, 0))).unwrap();
+        let (m, cast) = r.into_inner();
+        assert_eq!("\"", m.row_text(1).trim());
+        let cast = String::from_utf8(cast).unwrap();
+        let lines: Vec<&str> = cast.lines().collect();
+        assert_eq!(6, lines.len());
+        assert!(lines[0].starts_with("{\"version\": 2, \"width\": 3, \"height\": 2, \"timestamp\": "));
+        let events: Vec<&str> = lines[1 ..].iter().map(|l| &l[l.find(", ").unwrap() + 2 ..]).collect();
+        assert_eq!(vec![
+            "\"o\", \"\\u001b[?25l\\u001b[H\\u001b[2J\\u001b[2;2H\\u001b[0;1;31;49m\\\"\"]",
+            "\"i\", \"q\"]",
+            "\"i\", \"\\u001b[A\"]",
+            "\"r\", \"5x4\"]",
+            "\"o\", \"\\u001b[H\\u001b[2J\\u001b[1;1H\\u001b[?25h\"]",
+        ], events);
+    }
+
+    #[test]
+    fn records_bells() {
+        let mut r = Recorder::new(MemScr::new(1, 1), Vec::new()).unwrap();
+        r.beep().unwrap();
+        r.flash().unwrap();
+        r.refresh(None).unwrap();
+        let (_, cast) = r.into_inner();
+        let cast = String::from_utf8(cast).unwrap();
+        let o = cast.lines().nth(1).unwrap();
+        assert!(o.ends_with("\"o\", \"\\u001b[?25l\\u001b[H\\u001b[2J\\u0007\\u001b[?5h\\u001b[?5l\"]"), "{}", o);
+    }
+}
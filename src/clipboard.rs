@@ -0,0 +1,55 @@
+#![deny(warnings)]
+use scr::{ Scr, Error };
+
+// Copies go to an in-process buffer and to the system clipboard through the terminal (OSC 52).
+// Pastes come from the buffer, or from the terminal when querying is on and the terminal answers.
+pub struct Clipboard {
+    text: String,
+    query: bool,
+}
+
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard { text: String::new(), query: false }
+    }
+    pub fn text(&self) -> &str { &self.text }
+    // Off by default: most terminals refuse clipboard queries, which then costs a timeout on every paste.
+    // While on, `paste` blocks until the terminal answers, for up to 200ms with the ansi backend;
+    // input read meanwhile is kept for the following polls.
+    pub fn set_query(&mut self, query: bool) {
+        self.query = query;
+    }
+    pub fn copy(&mut self, scr: &mut Scr, text: &str) -> Result<(), Error> {
+        self.text = text.to_string();
+        scr.set_clipboard(text)
+    }
+    pub fn paste(&mut self, scr: &mut Scr) -> Result<String, Error> {
+        if self.query {
+            if let Some(text) = scr.get_clipboard()? { return Ok(text); }
+        }
+        Ok(self.text.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clipboard::Clipboard;
+    use mem::MemScr;
+    use scr::tests::TestScr;
+
+    #[test]
+    fn copy_and_paste() {
+        let mut c = Clipboard::new();
+        let mut s = TestScr::new(1, 1);
+        c.copy(&mut s, "inside").unwrap();
+        c.set_query(true);
+        assert_eq!(Ok("inside".to_string()), c.paste(&mut s));
+        let mut m = MemScr::new(1, 1);
+        c.copy(&mut m, "both").unwrap();
+        assert_eq!(Some("both"), m.clipboard());
+        m.set_system_clipboard(Some("outside".to_string()));
+        assert_eq!(Ok("outside".to_string()), c.paste(&mut m));
+        c.set_query(false);
+        assert_eq!(Ok("both".to_string()), c.paste(&mut m));
+    }
+}
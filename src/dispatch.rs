@@ -0,0 +1,280 @@
+#![deny(warnings)]
+use std::cmp::min;
+use std::collections::HashSet;
+use std::io;
+use std::mem::replace;
+use std::os::raw::{ c_int, c_void };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use libc::{ pipe, close, read, write, fcntl, poll, pollfd, POLLIN, F_GETFL, F_SETFL, O_NONBLOCK, EINTR };
+
+use scr::{ Scr, Event, Error };
+use fw::Fw;
+
+pub(crate) fn timeout_ms(timeout: Option<Duration>) -> c_int {
+    timeout.map_or(-1, |t| min(t.as_secs().saturating_mul(1000) + t.subsec_millis() as u64, c_int::max_value() as u64) as c_int)
+}
+
+fn os_error(call: &'static str) -> Error {
+    Error::Os { call: call, errno: io::Error::last_os_error().raw_os_error().unwrap_or(0) }
+}
+
+// Non-blocking on both ends, so writing from a signal handler is safe.
+pub(crate) struct Pipe {
+    pub(crate) rd: c_int,
+    pub(crate) wr: c_int,
+}
+
+impl Pipe {
+    pub(crate) fn new() -> Result<Pipe, Error> {
+        let mut fds = [0; 2];
+        if unsafe { pipe(fds.as_mut_ptr()) } != 0 { return Err(os_error("pipe")); }
+        let pipe = Pipe { rd: fds[0], wr: fds[1] };
+        for &fd in fds.iter() {
+            let flags = unsafe { fcntl(fd, F_GETFL) };
+            if flags < 0 || unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) } < 0 { return Err(os_error("fcntl")); }
+        }
+        Ok(pipe)
+    }
+    pub(crate) fn wake(&self) {
+        unsafe { write(self.wr, b"\0".as_ptr() as *const c_void, 1) };
+    }
+    fn drain(&self) {
+        let mut buf = [0u8; 64];
+        while unsafe { read(self.rd, buf.as_mut_ptr() as *mut c_void, buf.len()) } > 0 { }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe { close(self.rd) };
+        unsafe { close(self.wr) };
+    }
+}
+
+type Task<I> = Box<FnOnce(&Fw<I>) + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer<I : Send + Sync> {
+    id: TimerId,
+    due: Instant,
+    period: Option<Duration>,
+    callback: Box<FnMut(&Fw<I>) + Send>,
+}
+
+struct Timers<I : Send + Sync> {
+    next_id: u64,
+    list: Vec<Timer<I>>,
+    // Timers taken out of `list` by the ongoing `dispatch`; cancelling removes them from here too.
+    running: HashSet<TimerId>,
+}
+
+pub struct Poster<I : Send + Sync> {
+    tasks: Arc<Mutex<Vec<Task<I>>>>,
+    pipe: Arc<Pipe>,
+}
+
+impl<I : Send + Sync> Clone for Poster<I> {
+    fn clone(&self) -> Poster<I> {
+        Poster { tasks: Arc::clone(&self.tasks), pipe: Arc::clone(&self.pipe) }
+    }
+}
+
+impl<I : Send + Sync> Poster<I> {
+    pub fn post(&self, task: Box<FnOnce(&Fw<I>) + Send>) {
+        self.tasks.lock().unwrap().push(task);
+        self.pipe.wake();
+    }
+}
+
+pub struct Dispatcher<I : Send + Sync> {
+    tasks: Arc<Mutex<Vec<Task<I>>>>,
+    timers: Mutex<Timers<I>>,
+    pipe: Arc<Pipe>,
+}
+
+impl<I : Send + Sync> Dispatcher<I> {
+    pub fn new() -> Result<Dispatcher<I>, Error> {
+        Ok(Dispatcher {
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            timers: Mutex::new(Timers { next_id: 0, list: Vec::new(), running: HashSet::new() }),
+            pipe: Arc::new(Pipe::new()?),
+        })
+    }
+    pub fn poster(&self) -> Poster<I> {
+        Poster { tasks: Arc::clone(&self.tasks), pipe: Arc::clone(&self.pipe) }
+    }
+    pub fn wake(&self) {
+        self.pipe.wake();
+    }
+    fn add_timer(&self, delay: Duration, period: Option<Duration>, callback: Box<FnMut(&Fw<I>) + Send>) -> TimerId {
+        let mut timers = self.timers.lock().unwrap();
+        let id = TimerId(timers.next_id);
+        timers.next_id += 1;
+        timers.list.push(Timer { id: id, due: Instant::now() + delay, period: period, callback: callback });
+        id
+    }
+    pub fn set_timeout(&self, delay: Duration, callback: Box<FnMut(&Fw<I>) + Send>) -> TimerId {
+        self.add_timer(delay, None, callback)
+    }
+    pub fn set_interval(&self, period: Duration, callback: Box<FnMut(&Fw<I>) + Send>) -> TimerId {
+        self.add_timer(period, Some(period), callback)
+    }
+    pub fn cancel_timer(&self, id: TimerId) {
+        let mut timers = self.timers.lock().unwrap();
+        timers.list.retain(|t| t.id != id);
+        timers.running.remove(&id);
+    }
+    pub fn dispatch(&self, fw: &Fw<I>) {
+        let tasks = replace(&mut *self.tasks.lock().unwrap(), Vec::new());
+        for task in tasks {
+            task(fw);
+        }
+        let now = Instant::now();
+        let due: Vec<Timer<I>> = {
+            let mut timers = self.timers.lock().unwrap();
+            let (due, pending) = replace(&mut timers.list, Vec::new()).into_iter().partition(|t| t.due <= now);
+            timers.list = pending;
+            timers.running = due.iter().map(|t: &Timer<I>| t.id).collect();
+            due
+        };
+        for mut timer in due {
+            if !self.timers.lock().unwrap().running.contains(&timer.id) { continue; }
+            (timer.callback)(fw);
+            let mut timers = self.timers.lock().unwrap();
+            if !timers.running.remove(&timer.id) { continue; }
+            if let Some(period) = timer.period {
+                timer.due = timer.due + period;
+                if timer.due < now { timer.due = now + period; }
+                timers.list.push(timer);
+            }
+        }
+    }
+    pub fn wait(&self, scr: &mut Scr) -> Result<Option<Event>, Error> {
+        if let Some(e) = scr.poll(Some(Duration::from_millis(0)))? { return Ok(Some(e)); }
+        if !self.tasks.lock().unwrap().is_empty() { return Ok(None); }
+        let timeout = self.timers.lock().unwrap().list.iter().map(|t| t.due).min().map(|due| {
+            let now = Instant::now();
+            if due > now { due - now } else { Duration::from_millis(0) }
+        });
+        let timeout = match (timeout, scr.input_timeout()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+        let mut fds = [
+            pollfd { fd: self.pipe.rd, events: POLLIN, revents: 0 },
+            pollfd { fd: scr.input_fd().unwrap_or(-1), events: POLLIN, revents: 0 },
+            pollfd { fd: scr.signal_fd().unwrap_or(-1), events: POLLIN, revents: 0 },
+        ];
+        let n = unsafe { poll(fds.as_mut_ptr(), fds.len() as _, timeout_ms(timeout)) };
+        if n < 0 {
+            let e = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+            return if e == EINTR { Ok(None) } else { Err(Error::Os { call: "poll", errno: e }) };
+        }
+        self.pipe.drain();
+        if fds[1].revents != 0 || fds[2].revents != 0 {
+            return scr.poll(Some(Duration::from_millis(0)));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+    use std::thread;
+    use std::time::{ Duration, Instant };
+    use dispatch::Dispatcher;
+    use fw::Fw;
+    use mem::MemScr;
+    use scr::{ Event, Mods, Error };
+    use scr::tests::TestScr;
+
+    struct TestFw;
+
+    #[test]
+    fn input_comes_first() {
+        let fw = Fw::new(TestFw);
+        let dispatcher = Dispatcher::<TestFw>::new().unwrap();
+        let mut scr = MemScr::new(1, 1);
+        scr.push_char('a');
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let l = log.clone();
+        dispatcher.poster().post(Box::new(move |_| l.lock().unwrap().push("task")));
+        assert_eq!(Ok(Some(Event::Char('a', Mods::NONE))), dispatcher.wait(&mut scr));
+        assert_eq!(Err(Error::InputClosed), dispatcher.wait(&mut scr));
+        assert_eq!(Ok(None), dispatcher.wait(&mut TestScr::new(1, 1)));
+        dispatcher.dispatch(&fw);
+        assert_eq!(vec!["task"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn post_wakes_wait() {
+        let fw = Fw::new(TestFw);
+        let dispatcher = Dispatcher::<TestFw>::new().unwrap();
+        let mut scr = TestScr::new(1, 1);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let poster = dispatcher.poster();
+        let l = log.clone();
+        let worker = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            poster.post(Box::new(move |_| l.lock().unwrap().push("posted")));
+        });
+        assert_eq!(Ok(None), dispatcher.wait(&mut scr));
+        worker.join().unwrap();
+        dispatcher.dispatch(&fw);
+        assert_eq!(vec!["posted"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn timers() {
+        let fw = Fw::new(TestFw);
+        let dispatcher = Dispatcher::<TestFw>::new().unwrap();
+        let mut scr = TestScr::new(1, 1);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+        let l = log.clone();
+        dispatcher.set_timeout(Duration::from_millis(30), Box::new(move |_| l.lock().unwrap().push("timeout")));
+        let l = log.clone();
+        let interval = dispatcher.set_interval(Duration::from_millis(10), Box::new(move |_| l.lock().unwrap().push("interval")));
+        let l = log.clone();
+        let cancelled = dispatcher.set_timeout(Duration::from_millis(5), Box::new(move |_| l.lock().unwrap().push("cancelled")));
+        dispatcher.cancel_timer(cancelled);
+        while !log.lock().unwrap().contains(&"timeout") {
+            assert_eq!(Ok(None), dispatcher.wait(&mut scr));
+            dispatcher.dispatch(&fw);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        dispatcher.cancel_timer(interval);
+        let log = log.lock().unwrap();
+        assert!(log.iter().filter(|&&e| e == "interval").count() >= 2);
+        assert!(!log.contains(&"cancelled"));
+    }
+
+    #[test]
+    fn cancel_from_callbacks() {
+        let fw = Fw::new(TestFw);
+        let dispatcher = Arc::new(Dispatcher::<TestFw>::new().unwrap());
+        let count = Arc::new(Mutex::new(0));
+        let id = Arc::new(Mutex::new(None));
+        let (d, c, i) = (dispatcher.clone(), count.clone(), id.clone());
+        *id.lock().unwrap() = Some(dispatcher.set_interval(Duration::from_millis(1), Box::new(move |_| {
+            *c.lock().unwrap() += 1;
+            d.cancel_timer(i.lock().unwrap().unwrap());
+        })));
+        let fired = Arc::new(Mutex::new(false));
+        let later = Arc::new(Mutex::new(None));
+        let (d, l) = (dispatcher.clone(), later.clone());
+        dispatcher.set_timeout(Duration::from_millis(0), Box::new(move |_| d.cancel_timer(l.lock().unwrap().unwrap())));
+        let f = fired.clone();
+        *later.lock().unwrap() = Some(dispatcher.set_timeout(Duration::from_millis(1), Box::new(move |_| *f.lock().unwrap() = true)));
+        thread::sleep(Duration::from_millis(5));
+        dispatcher.dispatch(&fw);
+        thread::sleep(Duration::from_millis(5));
+        dispatcher.dispatch(&fw);
+        assert_eq!(1, *count.lock().unwrap());
+        assert!(!*fired.lock().unwrap());
+    }
+}
@@ -1,6 +1,6 @@
 use std::char::from_u32;
-use std::cmp::max;
-use scr::{ Color, Attr, Texel };
+use unicode_segmentation::UnicodeSegmentation;
+use scr::{ Color, Attr, Texel, Grapheme };
 use window::{ Rect, Window };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -52,13 +52,19 @@ impl ToTexel for Texel {
 
 impl ToTexel for char {
     fn texel(&self, attr: Attr, fg: Color, bg: Option<Color>) -> Texel {
-        Texel { ch: *self, attr: attr, fg: fg, bg: bg }
+        Texel { ch: (*self).into(), attr: attr, fg: fg, bg: bg }
+    }
+}
+
+impl ToTexel for Grapheme {
+    fn texel(&self, attr: Attr, fg: Color, bg: Option<Color>) -> Texel {
+        Texel { ch: self.clone(), attr: attr, fg: fg, bg: bg }
     }
 }
 
 impl ToTexel for Graph {
     fn texel(&self, attr: Attr, fg: Color, bg: Option<Color>) -> Texel {
-        Texel { ch: from_u32(*self as u8 as u32).unwrap(), attr: attr | Attr::ALTCHARSET, fg: fg, bg: bg }
+        Texel { ch: from_u32(*self as u8 as u32).unwrap().into(), attr: attr | Attr::ALTCHARSET, fg: fg, bg: bg }
     }
 }
 
@@ -71,8 +77,8 @@ pub fn draw_texel(window: &mut Window, y: isize, x: isize, t: &ToTexel, attr: At
 pub fn draw_h_line<'a, T: Into<Option<&'a ToTexel>>>(window: &mut Window, y: isize, x1: isize, x2: isize, ch: T, attr: Attr, fg: Color, bg: Option<Color>) {
     if let Some((x1, x2)) = window.area().inters_h_line(y, x1, x2) {
         let t = ch.into().unwrap_or(&Graph::HLine).texel(attr, fg, bg);
-        for x in x1 .. x2 {
-            window.out(y, x, t.clone());
+        for x in (x1 .. x2).step_by(t.width() as usize) {
+            window.out(y, x, if x + t.width() > x2 { t.narrow() } else { t.clone() });
         }
     }
 }
@@ -185,21 +191,28 @@ pub fn draw_text(window: &mut Window, y: isize, x: isize, text: &str, attr: Attr
     if y < 0 { return; }
     let (height, width) = window.bounds().size();
     if y >= height { return; }
-    let x0 = max(0, x);
-    let mut xi = x0;
-    for c in text.chars().skip((x0 - x) as usize) {
+    let mut xi = x;
+    for g in text.graphemes(true) {
         if xi >= width { return; }
-        let t = c.texel(attr, fg, bg);
-        window.out(y, xi, t);
-        xi += 1;
+        let t = Grapheme::from(g).texel(attr, fg, bg);
+        let w = t.width();
+        if xi >= 0 {
+            window.out(y, xi, t);
+        } else if xi + w > 0 {
+            window.out(y, 0, t.narrow());
+        }
+        xi += w;
     }
 }
 
 pub fn fill_rect(window: &mut Window, rect: &Rect, c: &ToTexel, attr: Attr, fg: Color, bg: Option<Color>) {
     let rect = window.area().inters_rect(rect);
     let t = c.texel(attr, fg, bg);
+    let (left, right) = rect.loc().map_or((0, 0), |(_, x)| (x, x + rect.size().1));
     rect.scan(|y, x| {
-        window.out(y, x, t.clone());
+        if (x - left) % t.width() == 0 {
+            window.out(y, x, if x + t.width() > right { t.narrow() } else { t.clone() });
+        }
         let continue_: Option<()> = None;
         continue_
     });
@@ -0,0 +1,210 @@
+#![deny(warnings)]
+use std::cmp::min;
+use std::os::raw::c_int;
+use std::time::Duration;
+use scr::{ Attr, Color, Scr, Texel, Key, Event, Error, CursorShape };
+
+fn blank() -> Texel {
+    Texel { ch: ' '.into(), attr: Attr::NORMAL, fg: Color::White, bg: None }
+}
+
+// Composes output into a back buffer and on refresh sends the wrapped screen only the cells
+// that differ from the last frame sent.
+pub struct Frame<S : Scr> {
+    scr: S,
+    height: isize,
+    width: isize,
+    back: Vec<Texel>,
+    front: Vec<Option<Texel>>,
+}
+
+impl<S : Scr> Frame<S> {
+    pub fn new(scr: S) -> Result<Frame<S>, Error> {
+        let mut frame = Frame { scr: scr, height: 0, width: 0, back: Vec::new(), front: Vec::new() };
+        frame.sync_size()?;
+        Ok(frame)
+    }
+    pub fn get_ref(&self) -> &S { &self.scr }
+    pub fn get_mut(&mut self) -> &mut S { &mut self.scr }
+    pub fn into_inner(self) -> S { self.scr }
+    // Forgets what the screen shows, so the next refresh sends the whole frame.
+    pub fn invalidate(&mut self) {
+        for t in self.front.iter_mut() {
+            *t = None;
+        }
+    }
+    fn sync_size(&mut self) -> Result<(), Error> {
+        let height = self.scr.get_height()?;
+        let width = self.scr.get_width()?;
+        if height == self.height && width == self.width { return Ok(()); }
+        let mut back = vec![blank(); (height * width) as usize];
+        for y in 0 .. min(height, self.height) {
+            for x in 0 .. min(width, self.width) {
+                back[(y * width + x) as usize] = self.back[(y * self.width + x) as usize].clone();
+            }
+        }
+        for y in 0 .. height {
+            let last = &mut back[(y * width + width - 1) as usize];
+            if last.width() > 1 { *last = last.narrow(); }
+        }
+        self.back = back;
+        self.front = vec![None; (height * width) as usize];
+        self.height = height;
+        self.width = width;
+        Ok(())
+    }
+    fn split_wide(&mut self, y: isize, x: isize) {
+        let other = {
+            let t = &self.back[(y * self.width + x) as usize];
+            if t.is_tail() { x - 1 } else if t.width() > 1 { x + 1 } else { return; }
+        };
+        if other < 0 || other >= self.width { return; }
+        let i = (y * self.width + other) as usize;
+        self.back[i] = Texel { ch: ' '.into(), ..self.back[i].clone() };
+    }
+    fn flush_row(&mut self, y: isize) -> Result<(), Error> {
+        let start = (y * self.width) as usize;
+        let end = start + self.width as usize;
+        let mut changed: Vec<bool> = (start .. end).map(|i| self.front[i].as_ref() != Some(&self.back[i])).collect();
+        // Halves of a double-width texel go out together.
+        for x in 0 .. changed.len() {
+            if !changed[x] { continue; }
+            let t = &self.back[start + x];
+            if t.is_tail() && x > 0 { changed[x - 1] = true; }
+            if t.width() > 1 && x + 1 < changed.len() { changed[x + 1] = true; }
+        }
+        let mut x = 0;
+        while x < changed.len() {
+            if !changed[x] { x += 1; continue; }
+            let first = x;
+            {
+                let t = &self.back[start + first];
+                while x < changed.len() && changed[x] && {
+                    let u = &self.back[start + x];
+                    u.attr == t.attr && u.fg == t.fg && u.bg == t.bg
+                } {
+                    x += 1;
+                }
+            }
+            self.scr.out_run(y, first as isize, &self.back[start + first .. start + x])?;
+            for i in start + first .. start + x {
+                self.front[i] = Some(self.back[i].clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S : Scr> Scr for Frame<S> {
+    fn get_height(&self) -> Result<isize, Error> { Ok(self.height) }
+    fn get_width(&self) -> Result<isize, Error> { Ok(self.width) }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
+        if y < 0 || x < 0 || y >= self.height || x >= self.width { return Err(Error::OutOfBounds { y: y, x: x }); }
+        if c.is_tail() {
+            if x > 0 && self.back[(y * self.width + x - 1) as usize].width() > 1 { return Ok(()); }
+            self.split_wide(y, x);
+            self.back[(y * self.width + x) as usize] = Texel { ch: ' '.into(), ..c.clone() };
+            return Ok(());
+        }
+        let c = if x + c.width() > self.width { c.narrow() } else { c.clone() };
+        self.split_wide(y, x);
+        if c.width() > 1 {
+            self.split_wide(y, x + 1);
+            self.back[(y * self.width + x + 1) as usize] = c.tail();
+        }
+        self.back[(y * self.width + x) as usize] = c;
+        Ok(())
+    }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
+        self.sync_size()?;
+        for y in 0 .. self.height {
+            self.flush_row(y)?;
+        }
+        self.scr.refresh(cursor)
+    }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> { self.scr.set_cursor_shape(shape) }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> { self.scr.set_title(title) }
+    fn beep(&mut self) -> Result<(), Error> { self.scr.beep() }
+    fn flash(&mut self) -> Result<(), Error> { self.scr.flash() }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> { self.scr.set_clipboard(text) }
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> { self.scr.get_clipboard() }
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        let e = self.scr.poll(timeout)?;
+        if let Some(Event::Key(Key::RESIZE, _)) = e { self.sync_size()?; }
+        Ok(e)
+    }
+    fn getch(&mut self) -> Result<Event, Error> {
+        let e = self.scr.getch()?;
+        if let Event::Key(Key::RESIZE, _) = e { self.sync_size()?; }
+        Ok(e)
+    }
+    fn set_esc_delay(&mut self, ms: u32) { self.scr.set_esc_delay(ms) }
+    fn input_fd(&self) -> Option<c_int> { self.scr.input_fd() }
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> { self.scr.terminal_fds() }
+    fn signal_fd(&self) -> Option<c_int> { self.scr.signal_fd() }
+    fn input_timeout(&self) -> Option<Duration> { self.scr.input_timeout() }
+    fn suspend(&mut self) -> Result<(), Error> { self.scr.suspend() }
+    fn resume(&mut self) -> Result<(), Error> {
+        self.scr.resume()?;
+        self.invalidate();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame::Frame;
+    use mem::MemScr;
+    use scr::{ Scr, Attr, Color, Texel };
+    use scr::tests::TestScr;
+
+    fn texel(c: char) -> Texel {
+        Texel { ch: c.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None }
+    }
+
+    #[test]
+    fn sends_only_changes() {
+        let mut f = Frame::new(TestScr::new(3, 10)).unwrap();
+        f.refresh(None).unwrap();
+        assert_eq!(3, f.get_ref().runs.len());
+        f.get_mut().runs.clear();
+        f.out(0, 0, &texel('a')).unwrap();
+        f.out(2, 9, &texel('b')).unwrap();
+        f.out(1, 3, &texel(' ')).unwrap();
+        f.out(1, 3, &Texel { attr: Attr::NORMAL, fg: Color::White, bg: None, ch: ' '.into() }).unwrap();
+        f.refresh(None).unwrap();
+        assert_eq!(vec![(0, 0, 1), (2, 9, 1)], f.get_ref().runs);
+        assert_eq!(texel('b'), *f.get_ref().content(2, 9));
+        f.get_mut().runs.clear();
+        f.out(0, 0, &texel('a')).unwrap();
+        f.refresh(None).unwrap();
+        assert!(f.get_ref().runs.is_empty());
+        f.invalidate();
+        f.refresh(None).unwrap();
+        assert_eq!(5, f.get_ref().runs.len());
+    }
+
+    #[test]
+    fn wide_chars_go_out_whole() {
+        let mut f = Frame::new(MemScr::new(1, 6)).unwrap();
+        f.out(0, 1, &texel('あ')).unwrap();
+        f.refresh(None).unwrap();
+        assert_eq!(" あ   ", f.get_ref().row_text(0));
+        f.out(0, 2, &texel('x')).unwrap();
+        f.refresh(None).unwrap();
+        assert_eq!("  x   ", f.get_ref().row_text(0));
+    }
+
+    #[test]
+    fn resize_resends_everything() {
+        let mut m = MemScr::new(2, 4);
+        m.push_resize(3, 5);
+        let mut f = Frame::new(m).unwrap();
+        f.out(1, 1, &texel('z')).unwrap();
+        f.refresh(None).unwrap();
+        f.poll(None).unwrap();
+        assert_eq!(5, f.get_width().unwrap());
+        f.refresh(None).unwrap();
+        assert_eq!(" z   ", f.get_ref().row_text(1));
+    }
+}
@@ -5,24 +5,47 @@ extern crate either;
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
-extern crate libc_extra;
 extern crate owning_ref;
+extern crate unicode_segmentation;
+extern crate unicode_width;
 
 pub mod scr;
+mod sgr;
+#[cfg(feature = "ncurses")]
 pub mod ncurses;
+#[cfg(feature = "ansi")]
+pub mod ansi;
+pub mod mem;
+pub mod frame;
+pub mod cast;
+pub mod replay;
+pub mod clipboard;
+pub mod dispatch;
 pub mod window;
 pub mod draw;
 #[macro_use]
 pub mod fw;
 pub mod inst;
 
+use std::collections::HashSet;
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::io::FromRawFd;
+use std::mem::replace;
 use std::ops::Deref;
+use std::process::{ Command, ExitStatus, Stdio };
 use std::sync::{ Arc, Mutex };
-use either::{ Left, Right };
-use ncurses::NCurses;
-use scr::{ Scr, Key };
+use libc::{ signal, raise, dup, SIGTSTP, SIG_DFL, STDIN_FILENO, STDOUT_FILENO };
+#[cfg(feature = "ncurses")]
+use ncurses::NCurses as Term;
+#[cfg(all(feature = "ansi", not(feature = "ncurses")))]
+use ansi::Ansi as Term;
+use scr::{ Scr, Key, Event, Error };
 use fw::{ ValType, ValTypeDesc, Fw, Val, DepType, Type, DepProp, Obj, ClassSetLock, DepObj, DepObjDataKey };
 use window::{ Rect, WindowsHost, Window };
+use dispatch::Dispatcher;
+use frame::Frame;
+use clipboard::Clipboard;
 
 struct StrTypeDesc { }
 impl<I : Send + Sync> ValTypeDesc<I> for StrTypeDesc {
@@ -91,7 +114,50 @@ impl<I : Send + Sync> ValTypeDesc<I> for RectTypeDesc {
     }
 }
 
-pub struct Ntfl<I : Send> {
+enum Job<I : Send + Sync> {
+    Suspend,
+    Run(Command, Box<FnOnce(io::Result<ExitStatus>, &Fw<I>) + Send>),
+    Copy(String),
+    Paste(Box<FnOnce(String, &Fw<I>) + Send>),
+}
+
+type InputHandler<I> = Box<Fn(&DepObj<I>, &Event, &Fw<I>) -> bool + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InputHandlerId(u64);
+
+struct InputHandlers<I : Send + Sync> {
+    next_id: u64,
+    list: Vec<(InputHandlerId, InputHandler<I>)>,
+    // Handlers taken out of `list` by the ongoing `route_input`; removing drops them from here too.
+    running: HashSet<InputHandlerId>,
+}
+
+// Runs `command` on the terminal of `scr` when that is not the process's own,
+// in place of any stdio set on it.
+fn attach_to_terminal(command: &mut Command, scr: &Scr) -> io::Result<()> {
+    let (fd_in, fd_out) = match scr.terminal_fds() {
+        Some(fds) if fds != (STDIN_FILENO, STDOUT_FILENO) => fds,
+        _ => return Ok(())
+    };
+    let stdio = |fd: c_int| -> io::Result<Stdio> {
+        let fd = unsafe { dup(fd) };
+        if fd < 0 { return Err(io::Error::last_os_error()); }
+        Ok(unsafe { Stdio::from_raw_fd(fd) })
+    };
+    command.stdin(stdio(fd_in)?).stdout(stdio(fd_out)?).stderr(stdio(fd_out)?);
+    Ok(())
+}
+
+fn stop_process() {
+    unsafe {
+        let handler = signal(SIGTSTP, SIG_DFL);
+        raise(SIGTSTP);
+        signal(SIGTSTP, handler);
+    }
+}
+
+pub struct Ntfl<I : Send + Sync> {
     str_type: ValType<I>,
     bool_type: ValType<I>,
     rect_type: ValType<I>,
@@ -100,11 +166,21 @@ pub struct Ntfl<I : Send> {
     visual_parent_prop: DepProp<I>,
     root_type: DepType<I>,
     root_bounds_lock: ClassSetLock,
+    visual_window: DepObjDataKey,
+    visual_input: DepObjDataKey,
+    focused: Mutex<Option<DepObj<I>>>,
     host: Arc<Mutex<WindowsHost>>,
+    dispatcher: Dispatcher<I>,
+    input_handlers: Mutex<InputHandlers<I>>,
+    exit_code: Mutex<Option<i32>>,
+    jobs: Mutex<Vec<Job<I>>>,
+    clipboard: Mutex<Clipboard>,
 }
 
 impl<I : 'static + Send + Sync> Ntfl<I> {
-    pub fn new(fw: &mut Fw<I>) -> Ntfl<I> {
+    // Fails if the dispatcher cannot make its wake pipe.
+    pub fn new(fw: &mut Fw<I>) -> Result<Ntfl<I>, Error> {
+        let dispatcher = Dispatcher::new()?;
         let host = Arc::new(Mutex::new(WindowsHost::new()));
         let str_type = fw.reg_val_type(Box::new(StrTypeDesc { }));
         let bool_type = fw.reg_val_type(Box::new(BoolTypeDesc { }));
@@ -158,7 +234,7 @@ impl<I : 'static + Send + Sync> Ntfl<I> {
                 }
             }));
         }
-        Ntfl {
+        Ok(Ntfl {
             str_type: str_type,
             bool_type: bool_type,
             rect_type: rect_type,
@@ -167,28 +243,170 @@ impl<I : 'static + Send + Sync> Ntfl<I> {
             visual_parent_prop: visual_parent_prop,
             root_type: root_type,
             root_bounds_lock: root_bounds_lock,
+            visual_window: visual_window,
+            visual_input: DepObjDataKey::new(),
+            focused: Mutex::new(None),
             host: host,
+            dispatcher: dispatcher,
+            input_handlers: Mutex::new(InputHandlers { next_id: 0, list: Vec::new(), running: HashSet::new() }),
+            exit_code: Mutex::new(None),
+            jobs: Mutex::new(Vec::new()),
+            clipboard: Mutex::new(Clipboard::new()),
+        })
+    }
+    // Global handlers see input first, in the order they were added; the first to return true consumes it.
+    pub fn on_input(&self, handler: Box<Fn(&DepObj<I>, &Event, &Fw<I>) -> bool + Send>) -> InputHandlerId {
+        let mut handlers = self.input_handlers.lock().unwrap();
+        let id = InputHandlerId(handlers.next_id);
+        handlers.next_id += 1;
+        handlers.list.push((id, handler));
+        id
+    }
+    pub fn remove_input_handler(&self, id: InputHandlerId) {
+        let mut handlers = self.input_handlers.lock().unwrap();
+        handlers.list.retain(|&(i, _)| i != id);
+        handlers.running.remove(&id);
+    }
+    // Input no global handler takes goes to the focused visual (or the root), then up through its parents.
+    pub fn on_visual_input(&self, visual: &DepObj<I>, handler: Box<Fn(&DepObj<I>, &Event, &Fw<I>) -> bool + Send>) {
+        visual.set_data(self.visual_input.clone(), Box::new(Arc::new(Mutex::new(handler))));
+    }
+    pub fn focus(&self, visual: &DepObj<I>) {
+        {
+            let window = visual.get_data(&self.visual_window);
+            window.borrow().unwrap().downcast_ref::<Mutex<Window>>().unwrap().lock().unwrap().focus();
         }
+        *self.focused.lock().unwrap() = Some(visual.clone());
     }
-    pub fn run(&self, root: &DepObj<I>, fw: &Fw<I>) {
-        let mut scr = NCurses::new().unwrap();
-        let update_root_bounds = |scr: &Scr| {
-            let height = scr.get_height().unwrap();
-            let width = scr.get_width().unwrap();
+    pub fn focused(&self) -> Option<DepObj<I>> { self.focused.lock().unwrap().clone() }
+    fn route_input(&self, root: &DepObj<I>, e: &Event, fw: &Fw<I>) -> bool {
+        let handlers = {
+            let mut handlers = self.input_handlers.lock().unwrap();
+            let list = replace(&mut handlers.list, Vec::new());
+            handlers.running = list.iter().map(|&(id, _)| id).collect();
+            list
+        };
+        let mut handled = false;
+        let mut kept = Vec::with_capacity(handlers.len());
+        for (id, handler) in handlers {
+            if !handled && self.input_handlers.lock().unwrap().running.contains(&id) {
+                handled = handler(root, e, fw);
+            }
+            kept.push((id, handler));
+        }
+        {
+            let mut handlers = self.input_handlers.lock().unwrap();
+            let running = replace(&mut handlers.running, HashSet::new());
+            kept.retain(|&(id, _)| running.contains(&id));
+            let added = replace(&mut handlers.list, Vec::new());
+            kept.extend(added);
+            handlers.list = kept;
+        }
+        if handled { return true; }
+        let mut visual = self.focused().unwrap_or_else(|| root.clone());
+        loop {
+            let handler = visual.get_data(&self.visual_input).borrow().map(|h| h.downcast_ref::<Arc<Mutex<InputHandler<I>>>>().unwrap().clone());
+            if let Some(handler) = handler {
+                if (handler.lock().unwrap())(&visual, e, fw) { return true; }
+            }
+            let parent = match visual.get(self.visual_parent_prop, fw).deref() {
+                Obj::Has(parent) => parent.dep().clone(),
+                _ => return false
+            };
+            visual = parent;
+        }
+    }
+    pub fn quit(&self, exit_code: i32) {
+        *self.exit_code.lock().unwrap() = Some(exit_code);
+        self.dispatcher.wake();
+    }
+    // Stops the process as Ctrl-Z does, restoring the terminal until it is continued.
+    pub fn suspend(&self) {
+        self.jobs.lock().unwrap().push(Job::Suspend);
+        self.dispatcher.wake();
+    }
+    // Runs `command` on the terminal (e.g. $EDITOR) and calls `done` once the screen is back.
+    // The terminal is the one `run_on` drives, so a program started for a session on its own pty runs there.
+    pub fn run_external(&self, command: Command, done: Box<FnOnce(io::Result<ExitStatus>, &Fw<I>) + Send>) {
+        self.jobs.lock().unwrap().push(Job::Run(command, done));
+        self.dispatcher.wake();
+    }
+    // Puts `text` on the clipboard of the app and, where the terminal lets it, of the system.
+    pub fn copy(&self, text: String) {
+        self.jobs.lock().unwrap().push(Job::Copy(text));
+        self.dispatcher.wake();
+    }
+    // Calls `done` with the clipboard text.
+    pub fn paste(&self, done: Box<FnOnce(String, &Fw<I>) + Send>) {
+        self.jobs.lock().unwrap().push(Job::Paste(done));
+        self.dispatcher.wake();
+    }
+    pub fn clipboard(&self) -> &Mutex<Clipboard> { &self.clipboard }
+    fn run_job(&self, scr: &mut Scr, job: Job<I>, fw: &Fw<I>) -> Result<(), Error> {
+        let done = match job {
+            Job::Copy(text) => return self.clipboard.lock().unwrap().copy(scr, &text),
+            Job::Paste(done) => {
+                let text = self.clipboard.lock().unwrap().paste(scr)?;
+                done(text, fw);
+                return Ok(());
+            },
+            Job::Suspend => { scr.suspend()?; stop_process(); None },
+            Job::Run(mut command, done) => {
+                if let Err(e) = attach_to_terminal(&mut command, scr) {
+                    done(Err(e), fw);
+                    return Ok(());
+                }
+                scr.suspend()?;
+                Some((command.status(), done))
+            },
+        };
+        scr.resume()?;
+        self.host.lock().unwrap().invalidate();
+        if let Some((status, done)) = done { done(status, fw); }
+        Ok(())
+    }
+    #[cfg(any(feature = "ncurses", feature = "ansi"))]
+    pub fn run(&self, root: &DepObj<I>, fw: &Fw<I>) -> Result<i32, Error> {
+        let mut scr = Term::new()?;
+        self.run_on(&mut scr, root, fw)
+    }
+    pub fn run_on(&self, scr: &mut Scr, root: &DepObj<I>, fw: &Fw<I>) -> Result<i32, Error> {
+        let mut frame = Frame::new(scr)?;
+        let scr = &mut frame;
+        let update_root_bounds = |scr: &Scr| -> Result<(), Error> {
+            let height = scr.get_height()?;
+            let width = scr.get_width()?;
             root.set_locked(self.visual_bounds_prop, Obj::Val(self.rect_type.box_(Rect::tlhw(0, 0, height, width))), &self.root_bounds_lock, fw);
+            Ok(())
         };
-        update_root_bounds(&scr);
+        update_root_bounds(scr)?;
         loop {
-            self.host.lock().unwrap().scr(&mut scr);
-            scr.refresh(None).unwrap();
-            match scr.getch().unwrap() {
-                Left(Key::RESIZE) => {
-                    update_root_bounds(&scr);
+            self.dispatcher.dispatch(fw);
+            let jobs = replace(&mut *self.jobs.lock().unwrap(), Vec::new());
+            for job in jobs {
+                self.run_job(scr, job, fw)?;
+            }
+            if let Some(exit_code) = self.exit_code.lock().unwrap().take() {
+                return Ok(exit_code);
+            }
+            let cursor = self.host.lock().unwrap().scr(scr)?;
+            scr.refresh(cursor)?;
+            let event = match self.dispatcher.wait(scr) {
+                // Line noise or an 8-bit Meta key: the backend has dropped the bad bytes.
+                Err(Error::InvalidUtf8(_)) => None,
+                event => event?
+            };
+            match event {
+                None => { },
+                Some(Event::Key(Key::RESIZE, _)) => {
+                    update_root_bounds(scr)?;
+                },
+                Some(Event::Key(Key::SUSPEND, _)) => {
+                    self.run_job(scr, Job::Suspend, fw)?;
                 },
-                Right('q') => {
-                    break;
+                Some(e) => {
+                    self.route_input(root, &e, fw);
                 }
-                _ => { }
             }
         }
     }
@@ -199,13 +417,22 @@ impl<I : 'static + Send + Sync> Ntfl<I> {
     pub fn visual_bounds_prop(&self) -> DepProp<I> { self.visual_bounds_prop }
     pub fn visual_parent_prop(&self) -> DepProp<I> { self.visual_parent_prop }
     pub fn root_type(&self) -> DepType<I> { self.root_type }
+    pub fn dispatcher(&self) -> &Dispatcher<I> { &self.dispatcher }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::mem::replace;
+    use std::os::raw::{ c_int, c_void };
+    use std::process::Command;
     use std::sync::{ Arc, Mutex };
+    use std::time::{ Duration, Instant };
+    use libc::{ pipe, read, close };
     use fw;
     use window::Rect;
+    use scr::{ Scr, Texel, Event, Mods, Error };
+    use mem::MemScr;
+    use replay::Replay;
 
     pub struct TestFw(());
     pub type Fw = fw::Fw<TestFw>;
@@ -218,7 +445,7 @@ mod tests {
     lazy_static! {
         static ref NTFL: (Mutex<Fw>, Ntfl) = {
             let mut fw = Fw::new(TestFw(()));
-            let ntfl = Ntfl::new(&mut fw);
+            let ntfl = Ntfl::new(&mut fw).unwrap();
             (Mutex::new(fw), ntfl)
         };
     }
@@ -230,15 +457,191 @@ mod tests {
         let v = NTFL.1.visual_type().create(fw);
         v.set(NTFL.1.visual_bounds_prop(), Obj_Val(NTFL.1.rect_type().box_(Rect::tlbr(3, 3, 10, 10))), fw).unwrap();
         v.set(NTFL.1.visual_parent_prop(), Obj_Has(Arc::new(Obj_Dep(r.clone()))), fw).unwrap();
-        NTFL.1.run(&r, fw);
+        let h = NTFL.1.on_input(Box::new(|_root, e, _fw| {
+            if *e != Event::Char('q', Mods::NONE) { return false; }
+            NTFL.1.quit(0);
+            true
+        }));
+        let mut scr = MemScr::new(12, 12);
+        scr.push_char('q');
+        assert_eq!(0, NTFL.1.run_on(&mut scr, &r, fw).unwrap());
+        NTFL.1.remove_input_handler(h);
+        assert_eq!(0, scr.pending_input());
+        assert_eq!("XXXXXXXXXXXX", scr.row_text(3));
+    }
+
+    #[test]
+    fn run_ends_with_script() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let mut scr = MemScr::new(2, 2);
+        scr.push_char('x');
+        assert_eq!(Err(Error::InputClosed), NTFL.1.run_on(&mut scr, &r, fw));
+        assert_eq!(0, scr.pending_input());
+    }
+
+    // Reports bad input once before the script, and claims a terminal on the given descriptors.
+    struct Noisy(MemScr, bool, Option<(c_int, c_int)>);
+
+    impl Scr for Noisy {
+        fn get_height(&self) -> Result<isize, Error> { self.0.get_height() }
+        fn get_width(&self) -> Result<isize, Error> { self.0.get_width() }
+        fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> { self.0.out(y, x, c) }
+        fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> { self.0.refresh(cursor) }
+        fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+            if replace(&mut self.1, false) { return Err(Error::InvalidUtf8(vec![0xFF])); }
+            self.0.poll(timeout)
+        }
+        fn terminal_fds(&self) -> Option<(c_int, c_int)> { self.2 }
+    }
+
+    #[test]
+    fn run_past_bad_input() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let mut scr = Noisy(MemScr::new(2, 2), true, None);
+        scr.0.push_char('x');
+        assert_eq!(Err(Error::InputClosed), NTFL.1.run_on(&mut scr, &r, fw));
+        assert_eq!(0, scr.0.pending_input());
+    }
+
+    #[test]
+    fn run_through_delay() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let mut scr = Replay::new(2, 2, "delay 20\ntext x").unwrap();
+        let start = Instant::now();
+        assert_eq!(Err(Error::InputClosed), NTFL.1.run_on(&mut scr, &r, fw));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(scr.is_finished());
+    }
+
+    #[test]
+    fn run_external() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let success = Arc::new(Mutex::new(None));
+        let s = success.clone();
+        let h = NTFL.1.on_input(Box::new(move |_root, e, _fw| {
+            if *e != Event::Char('e', Mods::NONE) { return false; }
+            let s = s.clone();
+            NTFL.1.run_external(Command::new("true"), Box::new(move |status, _fw| {
+                *s.lock().unwrap() = Some(status.unwrap().success());
+                NTFL.1.quit(7);
+            }));
+            true
+        }));
+        let mut scr = MemScr::new(4, 4);
+        scr.push_char('e');
+        assert_eq!(7, NTFL.1.run_on(&mut scr, &r, fw).unwrap());
+        NTFL.1.remove_input_handler(h);
+        assert_eq!(Some(true), *success.lock().unwrap());
+    }
+
+    #[test]
+    fn run_external_on_own_terminal() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { pipe(fds.as_mut_ptr()) });
+        let h = NTFL.1.on_input(Box::new(move |_root, e, _fw| {
+            if *e != Event::Char('e', Mods::NONE) { return false; }
+            let mut command = Command::new("echo");
+            command.arg("hi");
+            NTFL.1.run_external(command, Box::new(|status, _fw| {
+                assert!(status.unwrap().success());
+                NTFL.1.quit(4);
+            }));
+            true
+        }));
+        let mut scr = Noisy(MemScr::new(2, 2), false, Some((fds[0], fds[1])));
+        scr.0.push_char('e');
+        assert_eq!(4, NTFL.1.run_on(&mut scr, &r, fw).unwrap());
+        NTFL.1.remove_input_handler(h);
+        let mut buf = [0u8; 16];
+        let n = unsafe { read(fds[0], buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        assert_eq!(b"hi\n", &buf[.. n as usize]);
+        unsafe { close(fds[0]) };
+        unsafe { close(fds[1]) };
+    }
+
+    #[test]
+    fn copy_paste() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let pasted = Arc::new(Mutex::new(None));
+        let p = pasted.clone();
+        let h = NTFL.1.on_input(Box::new(move |_root, e, _fw| {
+            if *e != Event::Char('c', Mods::NONE) { return false; }
+            let p = p.clone();
+            NTFL.1.copy("copied".to_string());
+            NTFL.1.paste(Box::new(move |text, _fw| {
+                *p.lock().unwrap() = Some(text);
+                NTFL.1.quit(5);
+            }));
+            true
+        }));
+        let mut scr = MemScr::new(4, 4);
+        scr.push_char('c');
+        assert_eq!(5, NTFL.1.run_on(&mut scr, &r, fw).unwrap());
+        NTFL.1.remove_input_handler(h);
+        assert_eq!(Some("copied".to_string()), *pasted.lock().unwrap());
+        assert_eq!(Some("copied"), scr.clipboard());
+    }
+
+    #[test]
+    fn input_routing() {
+        let fw = &NTFL.0.lock().unwrap();
+        let r = NTFL.1.root_type().create(fw);
+        let v = NTFL.1.visual_type().create(fw);
+        v.set(NTFL.1.visual_parent_prop(), Obj_Has(Arc::new(Obj_Dep(r.clone()))), fw).unwrap();
+        let log = Arc::new(Mutex::new(String::new()));
+        let l = log.clone();
+        let added = Arc::new(Mutex::new(None));
+        let a = added.clone();
+        let first = NTFL.1.on_input(Box::new(move |_root, e, _fw| {
+            if *e != Event::Char('g', Mods::NONE) { return false; }
+            // Handlers may add and remove handlers.
+            let l = l.clone();
+            *a.lock().unwrap() = Some(NTFL.1.on_input(Box::new(move |_root, _e, _fw| {
+                l.lock().unwrap().push('h');
+                false
+            })));
+            true
+        }));
+        let l = log.clone();
+        let second = NTFL.1.on_input(Box::new(move |_root, e, _fw| {
+            l.lock().unwrap().push('s');
+            *e == Event::Char('g', Mods::NONE)
+        }));
+        let l = log.clone();
+        NTFL.1.on_visual_input(&v, Box::new(move |_v, e, _fw| {
+            l.lock().unwrap().push('v');
+            *e == Event::Char('v', Mods::NONE)
+        }));
+        let l = log.clone();
+        NTFL.1.on_visual_input(&r, Box::new(move |_r, e, _fw| {
+            l.lock().unwrap().push('r');
+            if *e == Event::Char('q', Mods::NONE) { NTFL.1.quit(3); }
+            true
+        }));
+        NTFL.1.focus(&v);
+        let mut scr = MemScr::new(4, 4);
+        scr.push_char('g');
+        scr.push_char('v');
+        scr.push_char('q');
+        assert_eq!(3, NTFL.1.run_on(&mut scr, &r, fw).unwrap());
+        NTFL.1.remove_input_handler(first);
+        NTFL.1.remove_input_handler(second);
+        NTFL.1.remove_input_handler(added.lock().unwrap().unwrap());
+        assert_eq!("shvshvr", *log.lock().unwrap());
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "ncurses"))]
 mod functional_tests {
-    use either::{ Left, Right };
     use ncurses::NCurses;
-    use scr::{ Scr, Color, Attr };
+    use scr::{ Scr, Color, Attr, Event };
     use window::{ Rect, WindowsHost };
     use draw::{ draw_border, draw_texel, Border, Graph, draw_text, fill_rect };
 
@@ -261,23 +664,23 @@ mod functional_tests {
         draw_border(&mut window, &Rect::tlbr(10, 0, 13, 40), &Border::new().ul(&Graph::LTee).ur(&Graph::RTee), Attr::BOLD, Color::Blue, None);
         draw_border(&mut window, &Rect::tlbr(0, 0, 10, 40), &Border::new().no_bottom(), Attr::BOLD, Color::Blue, None);
         draw_text(&mut window, 1, 1, "Aыcdefgh", Attr::NORMAL, Color::Green, None);
-        host.scr(&mut scr);
+        host.scr(&mut scr).unwrap();
         scr.refresh(Some((1, 1))).unwrap();
         let mut n = false;
         loop {
             n = !n;
             match scr.getch().unwrap() {
-                Left(_) => {
-                    let z_index = window.z_index();
-                    window.set_z_index(1 - z_index);
-                }
-                Right('\n') => { break; }
-                Right(c) => {
+                Event::Char('\n', _) => { break; }
+                Event::Char(c, _) => {
                     fill_rect(&mut bg, &bg_area, &' ', Attr::NORMAL, Color::Black, if n { Some(Color::Green) } else { None });
                     draw_texel(&mut window, 1, 1, &c, Attr::UNDERLINE, Color::Red, None);
                 }
+                _ => {
+                    let z_index = window.z_index();
+                    window.set_z_index(1 - z_index);
+                }
             }
-            host.scr(&mut scr);
+            host.scr(&mut scr).unwrap();
             scr.refresh(None).unwrap();
         }
     }
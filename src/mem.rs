@@ -0,0 +1,292 @@
+#![deny(warnings)]
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::time::Duration;
+use scr::{ Attr, Color, Scr, Texel, Key, Event, Mods, MouseEvent, Error, CursorShape };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemInput {
+    Event(Event),
+    Resize(isize, isize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleRun {
+    pub x: isize,
+    pub len: isize,
+    pub attr: Attr,
+    pub fg: Color,
+    pub bg: Option<Color>,
+}
+
+pub struct MemScr {
+    height: isize,
+    width: isize,
+    content: Vec<Texel>,
+    cursor: Option<(isize, isize)>,
+    cursor_shape: CursorShape,
+    title: Option<String>,
+    bells: usize,
+    flashes: usize,
+    clipboard: Option<String>,
+    input: VecDeque<MemInput>,
+}
+
+fn blank() -> Texel {
+    Texel { ch: ' '.into(), attr: Attr::NORMAL, fg: Color::White, bg: None }
+}
+
+impl MemScr {
+    pub fn new(height: isize, width: isize) -> MemScr {
+        MemScr {
+            height: height,
+            width: width,
+            content: vec![blank(); (height * width) as usize],
+            cursor: None,
+            cursor_shape: CursorShape::Default,
+            title: None,
+            bells: 0,
+            flashes: 0,
+            clipboard: None,
+            input: VecDeque::new(),
+        }
+    }
+    pub fn push(&mut self, input: MemInput) {
+        self.input.push_back(input);
+    }
+    pub fn push_event(&mut self, e: Event) {
+        self.push(MemInput::Event(e));
+    }
+    pub fn push_key(&mut self, key: Key) {
+        self.push_event(Event::Key(key, Mods::NONE));
+    }
+    pub fn push_char(&mut self, c: char) {
+        self.push_event(Event::Char(c, Mods::NONE));
+    }
+    pub fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push_char(c);
+        }
+    }
+    pub fn push_resize(&mut self, height: isize, width: isize) {
+        self.push(MemInput::Resize(height, width));
+    }
+    pub fn push_mouse(&mut self, e: MouseEvent) {
+        self.push_event(Event::Mouse(e));
+    }
+    pub fn pending_input(&self) -> usize { self.input.len() }
+    pub fn cursor(&self) -> Option<(isize, isize)> { self.cursor }
+    pub fn cursor_shape(&self) -> CursorShape { self.cursor_shape }
+    pub fn title(&self) -> Option<&str> { self.title.as_ref().map(|t| t.as_str()) }
+    pub fn bells(&self) -> usize { self.bells }
+    pub fn flashes(&self) -> usize { self.flashes }
+    // Stands for the system clipboard, which `get_clipboard` reads back.
+    pub fn clipboard(&self) -> Option<&str> { self.clipboard.as_ref().map(|t| t.as_str()) }
+    pub fn set_system_clipboard(&mut self, text: Option<String>) { self.clipboard = text; }
+    pub fn texel(&self, y: isize, x: isize) -> &Texel {
+        &self.content[(y * self.width + x) as usize]
+    }
+    pub fn row(&self, y: isize) -> &[Texel] {
+        let start = (y * self.width) as usize;
+        &self.content[start .. start + self.width as usize]
+    }
+    pub fn row_text(&self, y: isize) -> String {
+        self.row(y).iter().filter(|t| !t.is_tail()).map(|t| t.ch.to_string()).collect()
+    }
+    pub fn text(&self) -> String {
+        let rows: Vec<String> = (0 .. self.height).map(|y| self.row_text(y)).collect();
+        rows.join("\n")
+    }
+    pub fn style_runs(&self, y: isize) -> Vec<StyleRun> {
+        let mut runs: Vec<StyleRun> = Vec::new();
+        for (x, t) in self.row(y).iter().enumerate() {
+            if let Some(run) = runs.last_mut() {
+                if run.attr == t.attr && run.fg == t.fg && run.bg == t.bg {
+                    run.len += 1;
+                    continue;
+                }
+            }
+            runs.push(StyleRun { x: x as isize, len: 1, attr: t.attr, fg: t.fg, bg: t.bg });
+        }
+        runs
+    }
+    fn split_wide(&mut self, y: isize, x: isize) {
+        let other = {
+            let t = self.texel(y, x);
+            if t.is_tail() { x - 1 } else if t.width() > 1 { x + 1 } else { return; }
+        };
+        if other < 0 || other >= self.width { return; }
+        let i = (y * self.width + other) as usize;
+        self.content[i] = Texel { ch: ' '.into(), ..self.content[i].clone() };
+    }
+    fn resize(&mut self, height: isize, width: isize) {
+        let mut content = vec![blank(); (height * width) as usize];
+        for y in 0 .. min(height, self.height) {
+            for x in 0 .. min(width, self.width) {
+                content[(y * width + x) as usize] = self.content[(y * self.width + x) as usize].clone();
+            }
+        }
+        self.content = content;
+        self.height = height;
+        self.width = width;
+        self.cursor = None;
+    }
+}
+
+impl Scr for MemScr {
+    fn get_height(&self) -> Result<isize, Error> { Ok(self.height) }
+    fn get_width(&self) -> Result<isize, Error> { Ok(self.width) }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
+        if y < 0 || x < 0 || y >= self.height || x >= self.width { return Err(Error::OutOfBounds { y: y, x: x }); }
+        if c.is_tail() { return Ok(()); }
+        let c = if x + c.width() > self.width { c.narrow() } else { c.clone() };
+        self.split_wide(y, x);
+        if c.width() > 1 {
+            self.split_wide(y, x + 1);
+            self.content[(y * self.width + x + 1) as usize] = c.tail();
+        }
+        self.content[(y * self.width + x) as usize] = c;
+        Ok(())
+    }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
+        self.cursor = cursor;
+        Ok(())
+    }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> {
+        self.cursor_shape = shape;
+        Ok(())
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        self.title = Some(title.to_string());
+        Ok(())
+    }
+    fn beep(&mut self) -> Result<(), Error> {
+        self.bells += 1;
+        Ok(())
+    }
+    fn flash(&mut self) -> Result<(), Error> {
+        self.flashes += 1;
+        Ok(())
+    }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> {
+        self.clipboard = Some(text.to_string());
+        Ok(())
+    }
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.clipboard.clone())
+    }
+    // Once the script runs out the input counts as closed, so an event loop on a `MemScr` ends.
+    fn poll(&mut self, _timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        match self.input.pop_front() {
+            None => Err(Error::InputClosed),
+            Some(MemInput::Event(e)) => Ok(Some(e)),
+            Some(MemInput::Resize(height, width)) => {
+                self.resize(height, width);
+                Ok(Some(Event::Key(Key::RESIZE, Mods::NONE)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use mem::{ MemScr, StyleRun };
+    use scr::{ Attr, Color, Scr, Texel, Key, Event, Mods, MouseAction, MouseButton, MouseEvent, Error, Grapheme };
+    use window::{ Rect, WindowsHost };
+    use draw::{ draw_text, fill_rect };
+
+    #[test]
+    fn scripted_input() {
+        let mut scr = MemScr::new(2, 3);
+        scr.push_str("ab");
+        scr.push_key(Key::UP);
+        scr.push_resize(4, 5);
+        assert_eq!(Ok(Event::Char('a', Mods::NONE)), scr.getch());
+        assert_eq!(Ok(Event::Char('b', Mods::NONE)), scr.getch());
+        assert_eq!(Ok(Event::Key(Key::UP, Mods::NONE)), scr.getch());
+        assert_eq!(Ok(Event::Key(Key::RESIZE, Mods::NONE)), scr.getch());
+        assert_eq!((Ok(4), Ok(5)), (scr.get_height(), scr.get_width()));
+        let click = MouseEvent { y: 1, x: 2, button: Some(MouseButton::Left), action: MouseAction::Press, mods: Mods::NONE };
+        scr.push_mouse(click);
+        scr.push_event(Event::Char('x', Mods::CTRL | Mods::ALT));
+        assert_eq!(Ok(Event::Mouse(click)), scr.getch());
+        assert_eq!(Ok(Some(Event::Char('x', Mods::CTRL | Mods::ALT))), scr.poll(Some(Duration::from_millis(0))));
+        assert_eq!(Err(Error::InputClosed), scr.poll(Some(Duration::from_millis(10))));
+        assert_eq!(Err(Error::InputClosed), scr.getch());
+    }
+
+    #[test]
+    fn resize_keeps_content() {
+        let mut scr = MemScr::new(2, 2);
+        scr.out(0, 0, &Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: None }).unwrap();
+        scr.out(1, 1, &Texel { ch: 'z'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: None }).unwrap();
+        scr.push_resize(3, 1);
+        scr.getch().unwrap();
+        assert_eq!("a\n \n ", scr.text());
+        assert_eq!(Err(Error::OutOfBounds { y: 1, x: 1 }), scr.out(1, 1, &Texel { ch: 'z'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: None }));
+        scr.push_resize(2, 2);
+        scr.getch().unwrap();
+        assert_eq!("a \n  ", scr.text());
+    }
+
+    #[test]
+    fn rendered_text_and_styles() {
+        let mut scr = MemScr::new(2, 6);
+        let mut host = WindowsHost::new();
+        let mut window = host.new_window();
+        window.attach();
+        window.set_bounds(Rect::tlhw(0, 1, 1, 4));
+        draw_text(&mut window, 0, 0, "ab", Attr::BOLD, Color::Green, None);
+        draw_text(&mut window, 0, 2, "cd", Attr::NORMAL, Color::Red, Some(Color::Blue));
+        host.scr(&mut scr).unwrap();
+        scr.refresh(Some((0, 3))).unwrap();
+        assert_eq!(" abcd \n      ", scr.text());
+        assert_eq!(Some((0, 3)), scr.cursor());
+        assert_eq!(vec![
+            StyleRun { x: 0, len: 1, attr: Attr::NORMAL, fg: Color::White, bg: None },
+            StyleRun { x: 1, len: 2, attr: Attr::BOLD, fg: Color::Green, bg: None },
+            StyleRun { x: 3, len: 2, attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Blue) },
+            StyleRun { x: 5, len: 1, attr: Attr::NORMAL, fg: Color::White, bg: None },
+        ], scr.style_runs(0));
+    }
+
+    #[test]
+    fn wide_chars() {
+        let mut scr = MemScr::new(3, 6);
+        let mut host = WindowsHost::new();
+        let mut window = host.new_window();
+        window.attach();
+        window.set_bounds(Rect::tlhw(0, 0, 3, 5));
+        let area = window.area();
+        fill_rect(&mut window, &area, &'.', Attr::NORMAL, Color::White, None);
+        draw_text(&mut window, 0, 0, "日本a", Attr::NORMAL, Color::White, None);
+        draw_text(&mut window, 1, -1, "日本語", Attr::NORMAL, Color::White, None);
+        draw_text(&mut window, 2, 0, "日本語", Attr::NORMAL, Color::White, None);
+        host.scr(&mut scr).unwrap();
+        assert_eq!("日本a \n 本語 \n日本  ", scr.text());
+        draw_text(&mut window, 0, 1, "b", Attr::NORMAL, Color::White, None);
+        host.scr(&mut scr).unwrap();
+        assert_eq!(" b本a ", scr.row_text(0));
+        window.set_bounds(Rect::tlhw(0, -1, 3, 5));
+        host.scr(&mut scr).unwrap();
+        assert_eq!("b本aa ", scr.row_text(0));
+        assert_eq!("本語  ", scr.row_text(1));
+    }
+
+    #[test]
+    fn grapheme_clusters() {
+        let mut scr = MemScr::new(1, 8);
+        let mut host = WindowsHost::new();
+        let mut window = host.new_window();
+        window.attach();
+        window.set_bounds(Rect::tlhw(0, 0, 1, 8));
+        let area = window.area();
+        fill_rect(&mut window, &area, &'.', Attr::NORMAL, Color::White, None);
+        draw_text(&mut window, 0, 0, "e\u{301}\u{1F44D}\u{1F3FD}\u{2764}\u{FE0F}x", Attr::NORMAL, Color::White, None);
+        host.scr(&mut scr).unwrap();
+        assert_eq!("e\u{301}\u{1F44D}\u{1F3FD}\u{2764}\u{FE0F}x..", scr.row_text(0));
+        assert_eq!(&Grapheme::new("e\u{301}"), &scr.texel(0, 0).ch);
+        assert!(scr.texel(0, 2).is_tail());
+    }
+}
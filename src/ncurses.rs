@@ -1,33 +1,56 @@
 #![deny(warnings)]
-use std::char::from_u32;
+use std::collections::HashMap;
+use std::ffi::{ CStr, CString };
+use std::io;
 use std::marker::Sized;
 use std::os::raw::{ c_int, c_void, c_short, c_char, c_uint };
-use std::ptr::null;
-use either::{ Either, Left, Right };
-use libc::{ setlocale, LC_ALL, FILE };
-use libc_extra::unix::stdio::{ stdout, stdin };
+use std::mem::{ replace, zeroed };
+use std::ptr::{ null, null_mut };
+use std::slice;
+use std::str::from_utf8;
+use std::time::Duration;
+use libc::{ setlocale, LC_ALL, FILE, STDIN_FILENO, STDOUT_FILENO, wchar_t, dup, close, fdopen, fclose, fwrite, fflush };
 
-use scr::{ Color, Scr, Texel, Key };
+use scr::{ Attr, Color, Scr, Texel, Key, Event, Mods, MouseAction, MouseButton, MouseEvent, Error, CursorShape };
+use sgr::{ write_title, write_clipboard };
+use dispatch::timeout_ms;
 
 include!(concat!(env!("OUT_DIR"), "/c_bool.rs"));
 include!(concat!(env!("OUT_DIR"), "/ERR.rs"));
 include!(concat!(env!("OUT_DIR"), "/attr_t.rs"));
 include!(concat!(env!("OUT_DIR"), "/KEY_CODE_YES.rs"));
+include!(concat!(env!("OUT_DIR"), "/mmask_t.rs"));
+include!(concat!(env!("OUT_DIR"), "/mouse.rs"));
+include!(concat!(env!("OUT_DIR"), "/cchar_t.rs"));
+include!(concat!(env!("OUT_DIR"), "/CCHARW_MAX.rs"));
+
+const ESC_DELAY: c_int = 25;
 
 type WINDOW = c_void;
 type SCREEN = c_void;
 
+#[repr(C)]
+struct MEVENT {
+    id: c_short,
+    x: c_int,
+    y: c_int,
+    z: c_int,
+    bstate: mmask_t,
+}
+
 extern "C" {
     #[no_mangle]
     static stdscr: *mut WINDOW;
+    static COLORS: c_int;
+    static COLOR_PAIRS: c_int;
     fn newterm(type_: *mut c_char, outfd: *mut FILE, infd: *mut FILE) -> *mut SCREEN;
     fn endwin() -> c_int;
     fn delscreen(sp: *mut SCREEN);
+    fn set_term(new: *mut SCREEN) -> *mut SCREEN;
     fn noecho() -> c_int;
+    fn clearok(w: *mut WINDOW, bf: c_bool) -> c_int;
     fn wrefresh(w: *mut WINDOW) -> c_int;
     fn wmove(w: *mut WINDOW, y: c_int, x: c_int) -> c_int;
-    fn waddnstr(w: *mut WINDOW, s: *const c_char, n: c_int) -> c_int;
-    fn winsnstr(w: *mut WINDOW, s: *const c_char, n: c_int) -> c_int;
     fn wgetch(w: *mut WINDOW) -> c_int;
     fn getmaxx(w: *mut WINDOW) -> c_int;
     fn getmaxy(w: *mut WINDOW) -> c_int;
@@ -35,14 +58,29 @@ extern "C" {
     fn assume_default_colors(fg: c_int, bg: c_int) -> c_int;
     fn keypad(w: *mut WINDOW, bf: c_bool) -> c_int;
     fn init_pair(pair: c_short, f: c_short, b: c_short) -> c_int;
-    fn wattr_set(w: *mut WINDOW, attrs: attr_t, pair: c_short, opts: *const c_void) -> c_int;
+    fn setcchar(wcval: *mut cchar_t, wch: *const wchar_t, attrs: attr_t, color_pair: c_short, opts: *const c_void) -> c_int;
+    fn mvwadd_wchnstr(w: *mut WINDOW, y: c_int, x: c_int, wchstr: *const cchar_t, n: c_int) -> c_int;
     fn curs_set(visibility: c_int) -> c_int;
+    fn mousemask(newmask: mmask_t, oldmask: *mut mmask_t) -> mmask_t;
+    fn mouseinterval(erval: c_int) -> c_int;
+    fn getmouse(event: *mut MEVENT) -> c_int;
+    fn wtimeout(w: *mut WINDOW, delay: c_int);
+    fn set_escdelay(ms: c_int) -> c_int;
+    fn tigetstr(capname: *const c_char) -> *mut c_char;
+    fn key_defined(definition: *const c_char) -> c_int;
+    fn tiparm(s: *const c_char, ...) -> *mut c_char;
+    fn tigetflag(capname: *const c_char) -> c_int;
+    fn beep() -> c_int;
+    fn flash() -> c_int;
 }
 
 trait Checkable where Self: Sized {
     fn is_err(&self) -> bool;
-    fn check(self) -> Result<Self, ()> {
-        if self.is_err() { Err(()) } else { Ok(self) }
+    fn check(self, call: &'static str) -> Result<Self, Error> {
+        self.check_with(call, String::new)
+    }
+    fn check_with<F>(self, call: &'static str, context: F) -> Result<Self, Error> where F : FnOnce() -> String {
+        if self.is_err() { Err(Error::Curses { call: call, context: context() }) } else { Ok(self) }
     }
 }
 
@@ -60,67 +98,261 @@ impl Checkable for c_int {
 
 pub struct NCurses {
     screen: *mut SCREEN,
+    file_in: *mut FILE,
+    file_out: *mut FILE,
+    fd_in: c_int,
+    fd_out: c_int,
     stdscr: *mut WINDOW,
     cursor_is_visible: bool,
+    cursor_shape: CursorShape,
+    title: Option<String>,
+    colors: c_int,
+    color_pairs: c_int,
+    pairs: HashMap<(c_short, c_short), c_short>,
+    mouse_button: Option<MouseButton>,
+    ext_keys: HashMap<u32, (Key, Mods)>,
+    esc_delay: c_int,
 }
 
 impl NCurses {
-    pub fn new() -> Result<NCurses, ()> {
+    pub fn new() -> Result<NCurses, Error> {
+        NCurses::open(None, STDIN_FILENO, STDOUT_FILENO)
+    }
+    // Drives the terminal on the given descriptors, which stay open after drop.
+    // `term` defaults to $TERM.
+    pub fn open(term: Option<&str>, fd_in: c_int, fd_out: c_int) -> Result<NCurses, Error> {
+        fn fdopen_dup(fd: c_int, mode: &'static str) -> Result<*mut FILE, Error> {
+            let os_error = |call| Error::Os { call: call, errno: io::Error::last_os_error().raw_os_error().unwrap_or(0) };
+            let fd = unsafe { dup(fd) };
+            if fd < 0 { return Err(os_error("dup")); }
+            let file = unsafe { fdopen(fd, mode.as_ptr() as *const c_char) };
+            if file.is_null() {
+                let e = os_error("fdopen");
+                unsafe { close(fd) };
+                return Err(e);
+            }
+            Ok(file)
+        }
+        let term = match term {
+            None => None,
+            Some(term) => Some(CString::new(term).map_err(|_| Error::Curses { call: "newterm", context: format!("term = {:?}", term) })?)
+        };
         unsafe { setlocale(LC_ALL, "\0".as_ptr() as *const c_char) };
-        let screen = unsafe { newterm(null::<c_char>() as *mut c_char, stdout as *mut FILE, stdin as *mut FILE) }.check()?;
-        let stdscr_ = unsafe { stdscr }.check()?;
-        unsafe { start_color() }.check()?;
-        for bg in -1 .. 8 {
-        for fg in 0 .. 8 {
-            if fg == 0 && bg == -1 {
-                unsafe { assume_default_colors(0, -1) }
-            } else {
-                unsafe { init_pair(((1 + bg) << 3) | fg, fg, bg) }
-            }.check()?;
+        let file_in = fdopen_dup(fd_in, "r\0")?;
+        let file_out = match fdopen_dup(fd_out, "w\0") {
+            Ok(file) => file,
+            Err(e) => { unsafe { fclose(file_in) }; return Err(e); }
+        };
+        let term_ptr = term.as_ref().map_or(null(), |t| t.as_ptr()) as *mut c_char;
+        let screen = match unsafe { newterm(term_ptr, file_out, file_in) }.check_with("newterm", || format!("term = {:?}", term)) {
+            Ok(screen) => screen,
+            Err(e) => {
+                unsafe { fclose(file_in) };
+                unsafe { fclose(file_out) };
+                return Err(e);
+            }
+        };
+        let mut pairs = HashMap::new();
+        pairs.insert((0, -1), 0);
+        // From here on the screen and files belong to `scr`, so an early return cleans them up.
+        let mut scr = NCurses {
+            screen: screen,
+            file_in: file_in,
+            file_out: file_out,
+            fd_in: fd_in,
+            fd_out: fd_out,
+            stdscr: null_mut(),
+            cursor_is_visible: false,
+            cursor_shape: CursorShape::Default,
+            title: None,
+            colors: 0,
+            color_pairs: 0,
+            pairs: pairs,
+            mouse_button: None,
+            ext_keys: HashMap::new(),
+            esc_delay: ESC_DELAY
+        };
+        scr.stdscr = unsafe { stdscr }.check("stdscr")?;
+        unsafe { start_color() }.check("start_color")?;
+        unsafe { assume_default_colors(0, -1) }.check("assume_default_colors")?;
+        unsafe { noecho() }.check("noecho")?;
+        unsafe { keypad(scr.stdscr, 1) }.check("keypad")?;
+        unsafe { curs_set(0) };
+        unsafe { mousemask(ALL_MOUSE_EVENTS | REPORT_MOUSE_POSITION, null_mut()) };
+        unsafe { mouseinterval(0) };
+        unsafe { set_escdelay(ESC_DELAY) };
+        scr.colors = unsafe { COLORS };
+        scr.color_pairs = unsafe { COLOR_PAIRS };
+        scr.ext_keys = NCurses::ext_keys();
+        Ok(scr)
+    }
+    fn ext_keys() -> HashMap<u32, (Key, Mods)> {
+        const KEYS: [(&str, Key); 10] = [
+            ("kUP", Key::UP), ("kDN", Key::DOWN), ("kLFT", Key::LEFT), ("kRIT", Key::RIGHT), ("kHOM", Key::HOME),
+            ("kEND", Key::END), ("kIC", Key::IC), ("kDC", Key::DC), ("kPRV", Key::PPAGE), ("kNXT", Key::NPAGE),
+        ];
+
+        let mut ext_keys = HashMap::new();
+        for &(name, key) in KEYS.iter() {
+            for m in 2 .. 9 {
+                let cap = format!("{}{}\0", name, m);
+                let s = unsafe { tigetstr(cap.as_ptr() as *const c_char) };
+                if s.is_null() || s as isize == -1 { continue; }
+                let code = unsafe { key_defined(s) };
+                if code <= 0 { continue; }
+                ext_keys.insert(code as u32, (key, Mods::from_bits_truncate(m - 1)));
+            }
         }
+        ext_keys
+    }
+    // Pairs take palette indices only, so direct-colour terminals get the 256-colour palette too.
+    fn color(&self, c: Color) -> Result<c_short, Error> {
+        let c = c.approx(self.colors.max(0).min(256) as u32);
+        let n = c.index().ok_or_else(|| Error::Curses { call: "init_pair", context: format!("color = {:?}", c) })? as c_int;
+        let n = if n < self.colors { n } else { c.to_8().index().unwrap() as c_int };
+        Ok(n as c_short)
+    }
+    fn color_pair(&mut self, fg: Color, bg: Option<Color>) -> Result<c_short, Error> {
+        let fg = self.color(fg)?;
+        let bg = match bg { None => -1, Some(bg) => self.color(bg)? };
+        if let Some(&pair) = self.pairs.get(&(fg, bg)) { return Ok(pair); }
+        let pair = self.pairs.len() as c_int;
+        if pair >= self.color_pairs || pair > c_short::max_value() as c_int { return Ok(0); }
+        let pair = pair as c_short;
+        unsafe { init_pair(pair, fg, bg) }.check_with("init_pair", || format!("pair = {}, fg = {}, bg = {}", pair, fg, bg))?;
+        self.pairs.insert((fg, bg), pair);
+        Ok(pair)
+    }
+    fn read_mouse(&mut self) -> Result<MouseEvent, Error> {
+        const BUTTONS: [(mmask_t, MouseButton, MouseAction); 8] = [
+            (BUTTON1_PRESSED, MouseButton::Left, MouseAction::Press),
+            (BUTTON1_RELEASED, MouseButton::Left, MouseAction::Release),
+            (BUTTON2_PRESSED, MouseButton::Middle, MouseAction::Press),
+            (BUTTON2_RELEASED, MouseButton::Middle, MouseAction::Release),
+            (BUTTON3_PRESSED, MouseButton::Right, MouseAction::Press),
+            (BUTTON3_RELEASED, MouseButton::Right, MouseAction::Release),
+            (BUTTON4_PRESSED, MouseButton::WheelUp, MouseAction::Press),
+            (BUTTON5_PRESSED, MouseButton::WheelDown, MouseAction::Press),
+        ];
+
+        let mut e: MEVENT = unsafe { zeroed() };
+        unsafe { getmouse(&mut e) }.check("getmouse")?;
+        let mut mods = Mods::NONE;
+        if e.bstate & BUTTON_SHIFT != 0 { mods |= Mods::SHIFT; }
+        if e.bstate & BUTTON_ALT != 0 { mods |= Mods::ALT; }
+        if e.bstate & BUTTON_CTRL != 0 { mods |= Mods::CTRL; }
+        let (button, action) = match BUTTONS.iter().filter(|&&(mask, _, _)| mask != 0 && e.bstate & mask != 0).next() {
+            Some(&(_, button, action)) => (Some(button), action),
+            None if e.bstate & REPORT_MOUSE_POSITION != 0 => {
+                (self.mouse_button, if self.mouse_button.is_some() { MouseAction::Drag } else { MouseAction::Move })
+            },
+            None => return Err(Error::UnknownMouseEvent)
+        };
+        match (button, action) {
+            (Some(MouseButton::Left), MouseAction::Press) | (Some(MouseButton::Middle), MouseAction::Press) | (Some(MouseButton::Right), MouseAction::Press) => {
+                self.mouse_button = button;
+            },
+            (_, MouseAction::Release) => { self.mouse_button = None; },
+            _ => { }
         }
-        unsafe { noecho() }.check()?;
-        unsafe { keypad(stdscr_, 1) }.check()?;
-        unsafe { curs_set(0) };
-        Ok(NCurses { screen: screen, stdscr: stdscr_, cursor_is_visible: false })
+        Ok(MouseEvent { y: e.y as isize, x: e.x as isize, button: button, action: action, mods: mods })
     }
-    fn get_width_i(&self) -> Result<c_int, ()> {
-        unsafe { getmaxx(self.stdscr) }.check()
+    fn cchar(&mut self, c: &Texel) -> Result<cchar_t, Error> {
+        let pair = self.color_pair(c.fg, c.bg)?;
+        let attr = if self.colors < 16 && c.fg.is_bright() { c.attr | Attr::BOLD } else { c.attr };
+        let mut wch: [wchar_t; CCHARW_MAX + 1] = [0; CCHARW_MAX + 1];
+        for (i, ch) in c.ch.to_string().chars().take(CCHARW_MAX).enumerate() {
+            wch[i] = ch as wchar_t;
+        }
+        let mut cc: cchar_t = unsafe { zeroed() };
+        if unsafe { setcchar(&mut cc, wch.as_ptr(), (attr.bits() as attr_t) << 16, pair, null()) } == ERR {
+            // Curses refuses a cluster whose base it cannot print in this locale (and itself cuts
+            // others at the first spacing character). Losing the rest of the cluster is intended:
+            // the frame still draws, where an error would drop all of it.
+            wch[1] = 0;
+            unsafe { setcchar(&mut cc, wch.as_ptr(), (attr.bits() as attr_t) << 16, pair, null()) }.check_with("setcchar", || format!("ch = {:?}, attr = {:?}, pair = {}", c.ch, attr, pair))?;
+        }
+        Ok(cc)
+    }
+    fn add_cells(&self, y: isize, x: isize, cells: &[cchar_t]) -> Result<(), Error> {
+        if cells.is_empty() { return Ok(()); }
+        unsafe { mvwadd_wchnstr(self.stdscr, y as c_int, x as c_int, cells.as_ptr(), cells.len() as c_int) }.check_with("mvwadd_wchnstr", || format!("y = {}, x = {}, n = {}", y, x, cells.len()))?;
+        Ok(())
+    }
+    // Makes this terminal the current one for the calls not taking a window.
+    fn select(&self) {
+        unsafe { set_term(self.screen) };
+    }
+    // Not `putp`, which writes to stdout whatever terminal is current.
+    fn put(&self, bytes: &[u8]) {
+        unsafe { fwrite(bytes.as_ptr() as *const c_void, 1, bytes.len(), self.file_out) };
+        unsafe { fflush(self.file_out) };
+    }
+    // Needs the `Ss` extended capability (DECSCUSR in xterm-likes).
+    fn put_cursor_shape(&self, shape: CursorShape) {
+        let ss = unsafe { tigetstr("Ss\0".as_ptr() as *const c_char) };
+        if ss.is_null() || ss as isize == -1 { return; }
+        let s = unsafe { tiparm(ss, shape as c_int) };
+        if s.is_null() { return; }
+        self.put(unsafe { CStr::from_ptr(s) }.to_bytes());
+    }
+    // Terminals flagged `XT` understand OSC titles and clipboard, and the xterm title stack.
+    fn has_osc(&self) -> bool {
+        unsafe { tigetflag("XT\0".as_ptr() as *const c_char) > 0 }
+    }
+    fn put_title(&self, push: bool) {
+        if let Some(ref title) = self.title {
+            let mut buf = Vec::new();
+            if push { buf.extend_from_slice(b"\x1b[22;0t"); }
+            write_title(&mut buf, title);
+            self.put(&buf);
+        }
     }
-    fn get_height_i(&self) -> Result<c_int, ()> {
-        unsafe { getmaxy(self.stdscr) }.check()
+    fn get_width_i(&self) -> Result<c_int, Error> {
+        unsafe { getmaxx(self.stdscr) }.check("getmaxx")
+    }
+    fn get_height_i(&self) -> Result<c_int, Error> {
+        unsafe { getmaxy(self.stdscr) }.check("getmaxy")
     }
 }
 
 impl Scr for NCurses {
-    fn get_width(&self) -> Result<isize, ()> {
+    fn get_width(&self) -> Result<isize, Error> {
         let w = self.get_width_i()?;
         Ok(w as isize)
     }
-    fn get_height(&self) -> Result<isize, ()> {
+    fn get_height(&self) -> Result<isize, Error> {
         let h = self.get_height_i()?;
         Ok(h as isize)
     }
-    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), ()> {
-        fn color_pair(fg: Color, bg: Option<Color>) -> c_short {
-            let bg = match bg {
-                Some(c) => 1 + (c as i8 as c_short),
-                None => 0
-            };
-            (bg << 3) | (fg as i8 as c_short)
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
+        self.out_run(y, x, slice::from_ref(c))
+    }
+    fn out_run(&mut self, y: isize, x: isize, texels: &[Texel]) -> Result<(), Error> {
+        self.select();
+        let width = self.get_width_i()? as isize;
+        let mut cells = Vec::with_capacity(texels.len());
+        let mut start = x;
+        let mut xi = x;
+        let mut wide = false;
+        for c in texels {
+            xi += 1;
+            if c.is_tail() {
+                if replace(&mut wide, false) { continue; }
+                // The head is out of this run: write what came before and go on past the tail.
+                self.add_cells(y, start, &cells)?;
+                cells.clear();
+                start = xi;
+                continue;
+            }
+            let c = if xi - 1 + c.width() > width { c.narrow() } else { c.clone() };
+            cells.push(self.cchar(&c)?);
+            wide = c.width() > 1;
         }
-
-        let y = y as c_int;
-        let x = x as c_int;
-        unsafe { wmove(self.stdscr, y, x) }.check()?;
-        unsafe { wattr_set(self.stdscr, (c.attr.bits() as attr_t) << 16, color_pair(c.fg, c.bg), null()) }.check()?;
-        let outstr = if x + 1 < self.get_width_i()? { waddnstr } else { winsnstr };
-        let mut b = [0; 6];
-        let b = c.ch.encode_utf8(&mut b);
-        unsafe { outstr(self.stdscr, b.as_bytes().as_ptr() as *const c_char, b.len() as c_int) }.check()?;
-        Ok(())
+        self.add_cells(y, start, &cells)
     }
-    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), ()> {
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
+        self.select();
         match cursor {
             None => {
                 if self.cursor_is_visible {
@@ -135,53 +367,234 @@ impl Scr for NCurses {
                     unsafe { curs_set(1); }
                     self.cursor_is_visible = true;
                 }
-                unsafe { wmove(self.stdscr, y, x) }.check()?;
+                unsafe { wmove(self.stdscr, y, x) }.check_with("wmove", || format!("y = {}, x = {}", y, x))?;
             }
         }
-        unsafe { wrefresh(self.stdscr) }.check()?;
+        unsafe { wrefresh(self.stdscr) }.check("wrefresh")?;
+        Ok(())
+    }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> {
+        if shape == self.cursor_shape { return Ok(()); }
+        self.select();
+        self.put_cursor_shape(shape);
+        self.cursor_shape = shape;
+        Ok(())
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        self.select();
+        if !self.has_osc() { return Ok(()); }
+        let push = self.title.is_none();
+        self.title = Some(title.to_string());
+        self.put_title(push);
         Ok(())
     }
-    fn getch(&mut self) -> Result<Either<Key, char>, ()> {
-        fn read_u8_tail<G>(b0: u8, g: &G) -> Result<u32, ()> where G : Fn() -> Result<u8, ()> {
-            let next = || -> Result<u8, ()> {
-                let bi = g()?;
-                if bi & 0xC0 != 0x80 { return Err(()); }
-                Ok(bi & 0x3F)
+    // Replies to clipboard queries would come mixed with keys from `wgetch`, so `get_clipboard` is left out.
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> {
+        self.select();
+        if !self.has_osc() { return Ok(()); }
+        let mut buf = Vec::new();
+        write_clipboard(&mut buf, text);
+        self.put(&buf);
+        Ok(())
+    }
+    fn beep(&mut self) -> Result<(), Error> {
+        self.select();
+        unsafe { beep() };
+        Ok(())
+    }
+    fn flash(&mut self) -> Result<(), Error> {
+        self.select();
+        unsafe { flash() };
+        Ok(())
+    }
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        self.select();
+        unsafe { wtimeout(self.stdscr, timeout_ms(timeout)) };
+        let b0 = unsafe { wgetch(self.stdscr) };
+        unsafe { wtimeout(self.stdscr, -1) };
+        if b0 == ERR && timeout.is_some() { return Ok(None); }
+        let b0 = b0.check("wgetch")?;
+        let alt = b0 == 0x1B;
+        let b0 = if alt {
+            unsafe { wtimeout(self.stdscr, self.esc_delay) };
+            let b1 = unsafe { wgetch(self.stdscr) };
+            unsafe { wtimeout(self.stdscr, -1) };
+            if b1 == ERR { return Ok(Some(Event::Char('\x1B', Mods::NONE))); }
+            b1
+        } else {
+            b0
+        } as c_uint;
+        let e = if b0 >= KEY_CODE_YES {
+            let key = Key { value: b0 as u32 };
+            if key == Key::MOUSE {
+                Event::Mouse(self.read_mouse()?)
+            } else if let Some(&(key, mods)) = self.ext_keys.get(&key.value) {
+                Event::Key(key, mods)
+            } else {
+                Event::from(key)
+            }
+        } else {
+            let mut bytes = vec![b0 as u8];
+            let len = match b0 {
+                0x00 ..= 0x7F => 1,
+                0xC0 ..= 0xDF => 2,
+                0xE0 ..= 0xEF => 3,
+                0xF0 ..= 0xF7 => 4,
+                _ => return Err(Error::InvalidUtf8(bytes))
             };
-            if b0 & 0x80 == 0 { return Ok(b0 as u32); }
-            if b0 & 0x40 == 0 { return Err(()); }
-            let b1 = next()?;
-            if b0 & 0x20 == 0 { return Ok((((b0 & 0x1F) as u32) << 6) | (b1 as u32)); }
-            let b2 = next()?;
-            if b0 & 0x10 == 0 { return Ok((((b0 & 0x0F) as u32) << 12) | ((b1 as u32) << 6) | (b2 as u32)); }
-            let b3 = next()?;
-            if b0 & 0x08 == 0 { return Ok((((b0 & 0x07) as u32) << 18) | ((b1 as u32) << 12) | ((b2 as u32) << 6) | (b3 as u32)); }
-            let b4 = next()?;
-            if b0 & 0x04 == 0 { return Ok((((b0 & 0x03) as u32) << 24) | ((b1 as u32) << 18) | ((b2 as u32) << 12) | ((b3 as u32) << 6) | (b4 as u32)); }
-            let b5 = next()?;
-            if b0 & 0x02 == 0 { return Ok((((b0 & 0x01) as u32) << 30) | ((b1 as u32) << 24) | ((b2 as u32) << 18) | ((b3 as u32) << 12) | ((b4 as u32) << 6) | (b5 as u32)); }
-            Err(())
-        }
-
-        let b0 = unsafe { wgetch(self.stdscr) }.check()? as c_uint;
-        if b0 & KEY_CODE_YES != 0 {
-            return Ok(Left(Key { value: b0 as u32 }));
-        }
-        let c = read_u8_tail(b0 as u8, &|| {
-            let bi = unsafe { wgetch(self.stdscr) }.check()? as c_uint;
-            if bi & KEY_CODE_YES != 0 { return Err(()); }
-            Ok(bi as u8)
-        })?;
-        match from_u32(c) {
-            Some(x) => Ok(Right(x)),
-            None => Err(())
-        }
+            while bytes.len() < len {
+                let bi = unsafe { wgetch(self.stdscr) }.check("wgetch")? as c_uint;
+                if bi >= KEY_CODE_YES || bi & 0xC0 != 0x80 { return Err(Error::InvalidUtf8(bytes)); }
+                bytes.push(bi as u8);
+            }
+            match from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(x) => Event::decode_char(x),
+                None => return Err(Error::InvalidUtf8(bytes))
+            }
+        };
+        Ok(Some(if alt { e.with_mods(Mods::ALT) } else { e }))
+    }
+    fn set_esc_delay(&mut self, ms: u32) {
+        self.select();
+        self.esc_delay = ms as c_int;
+        unsafe { set_escdelay(self.esc_delay) };
+    }
+    fn input_fd(&self) -> Option<c_int> {
+        Some(self.fd_in)
+    }
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> {
+        Some((self.fd_in, self.fd_out))
+    }
+    fn suspend(&mut self) -> Result<(), Error> {
+        self.select();
+        if self.cursor_shape != CursorShape::Default { self.put_cursor_shape(CursorShape::Default); }
+        if self.title.is_some() { self.put(b"\x1b[23;0t"); }
+        unsafe { endwin() }.check("endwin")?;
+        Ok(())
+    }
+    fn resume(&mut self) -> Result<(), Error> {
+        self.select();
+        if self.cursor_shape != CursorShape::Default { self.put_cursor_shape(self.cursor_shape); }
+        self.put_title(true);
+        unsafe { clearok(self.stdscr, 1) }.check("clearok")?;
+        Ok(())
     }
 }
 
 impl Drop for NCurses {
     fn drop(&mut self) {
+        self.select();
+        if self.cursor_shape != CursorShape::Default { self.put_cursor_shape(CursorShape::Default); }
+        if self.title.is_some() { self.put(b"\x1b[23;0t"); }
         unsafe { endwin(); }
         unsafe { delscreen(self.screen); }
+        unsafe { fclose(self.file_in) };
+        unsafe { fclose(self.file_out) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::mem::zeroed;
+    use std::os::raw::{ c_int, c_short, c_void };
+    use std::ptr::null_mut;
+    use libc::{ posix_openpt, grantpt, unlockpt, ptsname, open, close, winsize, ioctl, TIOCSWINSZ, O_RDWR, O_NOCTTY, wchar_t };
+    use ncurses::{ NCurses, WINDOW, cchar_t, attr_t, CCHARW_MAX };
+    use scr::{ Scr, Attr, Color, Error, Texel };
+
+    extern "C" {
+        fn mvwin_wch(w: *mut WINDOW, y: c_int, x: c_int, wcval: *mut cchar_t) -> c_int;
+        fn getcchar(wcval: *const cchar_t, wch: *mut wchar_t, attrs: *mut attr_t, color_pair: *mut c_short, opts: *mut c_void) -> c_int;
+    }
+
+    fn with_pty<F>(f: F) where F : FnOnce(c_int) {
+        let master = unsafe { posix_openpt(O_RDWR | O_NOCTTY) };
+        assert!(master >= 0);
+        assert_eq!(0, unsafe { grantpt(master) });
+        assert_eq!(0, unsafe { unlockpt(master) });
+        let name = unsafe { CStr::from_ptr(ptsname(master)) }.to_owned();
+        let slave = unsafe { open(name.as_ptr(), O_RDWR | O_NOCTTY) };
+        assert!(slave >= 0);
+        let ws = winsize { ws_row: 3, ws_col: 10, ws_xpixel: 0, ws_ypixel: 0 };
+        assert_eq!(0, unsafe { ioctl(master, TIOCSWINSZ, &ws) });
+        f(slave);
+        unsafe { close(slave) };
+        unsafe { close(master) };
+    }
+
+    fn cell(scr: &NCurses, y: c_int, x: c_int) -> String {
+        let mut cc: cchar_t = unsafe { zeroed() };
+        assert!(unsafe { mvwin_wch(scr.stdscr, y, x, &mut cc) } >= 0);
+        let mut wch: [wchar_t; CCHARW_MAX + 1] = [0; CCHARW_MAX + 1];
+        let mut attrs: attr_t = 0;
+        let mut pair: c_short = 0;
+        unsafe { getcchar(&cc, wch.as_mut_ptr(), &mut attrs, &mut pair, null_mut()) };
+        wch.iter().take_while(|&&c| c != 0).filter_map(|&c| ::std::char::from_u32(c as u32)).collect()
+    }
+
+    #[test]
+    fn run_starting_on_tail() {
+        with_pty(|fd| {
+            let scr = &mut NCurses::open(Some("xterm-256color"), fd, fd).unwrap();
+            let wide = Texel { ch: '日'.into(), attr: Attr::NORMAL, fg: Color::White, bg: None };
+            let a = Texel { ch: 'a'.into(), ..wide.clone() };
+            let b = Texel { ch: 'b'.into(), ..wide.clone() };
+            scr.out_run(1, 2, &[wide.tail(), a, b]).unwrap();
+            assert_eq!("a", cell(scr, 1, 3));
+            assert_eq!("b", cell(scr, 1, 4));
+            assert_eq!(" ", cell(scr, 1, 2));
+        });
+    }
+
+    #[test]
+    fn run_with_tail_inside() {
+        with_pty(|fd| {
+            let scr = &mut NCurses::open(Some("xterm-256color"), fd, fd).unwrap();
+            let wide = Texel { ch: '日'.into(), attr: Attr::NORMAL, fg: Color::White, bg: None };
+            let a = Texel { ch: 'a'.into(), ..wide.clone() };
+            let b = Texel { ch: 'b'.into(), ..wide.clone() };
+            scr.out_run(0, 0, &[a, wide.tail(), b]).unwrap();
+            assert_eq!("a", cell(scr, 0, 0));
+            assert_eq!(" ", cell(scr, 0, 1));
+            assert_eq!("b", cell(scr, 0, 2));
+        });
+    }
+
+    #[test]
+    fn cluster_falls_back_to_base() {
+        with_pty(|fd| {
+            let scr = &mut NCurses::open(Some("xterm-256color"), fd, fd).unwrap();
+            let texel = |s: &str| Texel { ch: s.into(), attr: Attr::NORMAL, fg: Color::White, bg: None };
+            // U+0378 is unassigned, so unprintable whatever the locale.
+            scr.out_run(0, 0, &[texel("\u{378}\u{301}"), texel("a")]).unwrap();
+            assert_eq!("\u{378}", cell(scr, 0, 0));
+            assert_eq!("a", cell(scr, 0, 1));
+        });
+    }
+
+    #[test]
+    fn direct_color_uses_palette() {
+        with_pty(|fd| {
+            let scr = &mut NCurses::open(Some("xterm-256color"), fd, fd).unwrap();
+            scr.colors = 1 << 24;
+            let texel = Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Rgb(255, 0, 0), bg: Some(Color::Rgb(0, 0, 0)) };
+            scr.out_run(0, 0, &[texel]).unwrap();
+            assert_eq!(Ok(196), scr.color(Color::Rgb(255, 0, 0)));
+            assert_eq!(Ok(16), scr.color(Color::Rgb(0, 0, 0)));
+        });
+    }
+
+    #[test]
+    fn open_failure_releases_screen() {
+        with_pty(|fd| {
+            // vt100 has no default colors, so setup fails after newterm.
+            match NCurses::open(Some("vt100"), fd, fd) {
+                Err(Error::Curses { call: "assume_default_colors", .. }) => { },
+                r => panic!("{:?}", r.map(|_| ()))
+            }
+            let scr = &mut NCurses::open(Some("xterm-256color"), fd, fd).unwrap();
+            assert_eq!(10, scr.get_width().unwrap());
+        });
     }
 }
@@ -0,0 +1,289 @@
+#![deny(warnings)]
+use std::collections::VecDeque;
+use std::error;
+use std::fmt::{ self, Display, Formatter };
+use std::thread::sleep;
+use std::time::{ Duration, Instant };
+
+use mem::MemScr;
+use scr::{ Scr, Texel, Key, Event, Mods, Error, CursorShape };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Event(Event),
+    Resize(isize, isize),
+    Delay(Duration),
+    // A delay under way.
+    Until(Instant),
+    Check { line: usize, rows: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub row: isize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}, row {}: expected {:?}, got {:?}", self.line, self.row, self.expected, self.actual)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "invalid script line {}: {:?}", self.line, self.text)
+    }
+}
+
+impl error::Error for ScriptError { }
+
+fn parse_key(name: &str) -> Option<Event> {
+    let mut mods = Mods::NONE;
+    let mut name = name;
+    while name.len() > 2 && name.as_bytes()[1] == b'-' {
+        mods |= match name.as_bytes()[0] {
+            b'S' => Mods::SHIFT,
+            b'M' | b'A' => Mods::ALT,
+            b'C' => Mods::CTRL,
+            _ => return None
+        };
+        name = &name[2 ..];
+    }
+    let key = match name {
+        "UP" => Key::UP,
+        "DOWN" => Key::DOWN,
+        "LEFT" => Key::LEFT,
+        "RIGHT" => Key::RIGHT,
+        "HOME" => Key::HOME,
+        "END" => Key::END,
+        "PPAGE" => Key::PPAGE,
+        "NPAGE" => Key::NPAGE,
+        "IC" => Key::IC,
+        "DC" => Key::DC,
+        "BACKSPACE" => Key::BACKSPACE,
+        "ENTER" => Key::ENTER,
+        "BTAB" => Key::BTAB,
+        "ESC" => return Some(Event::Char('\x1B', mods)),
+        "TAB" => return Some(Event::Char('\t', mods)),
+        "RET" => return Some(Event::Char('\n', mods)),
+        "SPACE" => return Some(Event::Char(' ', mods)),
+        n if n.starts_with('F') && n.len() > 1 => match n[1 ..].parse::<u8>() {
+            Ok(n) if n <= 64 => Key::f(n),
+            _ => return None
+        },
+        n => {
+            let mut chars = n.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(Event::Char(c, mods)),
+                _ => None
+            };
+        }
+    };
+    // As the backends report it: e.g. BTAB is Shift-Tab.
+    Some(match Event::from(key) {
+        Event::Key(key, m) => Event::Key(key, m | mods),
+        Event::Char(c, m) => Event::Char(c, m | mods),
+        e => e
+    })
+}
+
+fn parse(script: &str) -> Result<VecDeque<Step>, ScriptError> {
+    let mut steps = VecDeque::new();
+    let mut in_check = false;
+    for (i, text) in script.lines().enumerate() {
+        let line = i + 1;
+        let invalid = || ScriptError { line: line, text: text.to_string() };
+        if text.starts_with('|') {
+            let row = &text[1 ..];
+            let row = if row.ends_with('|') { &row[.. row.len() - 1] } else { row };
+            let row = row.trim_end().to_string();
+            if in_check {
+                if let Some(&mut Step::Check { ref mut rows, .. }) = steps.back_mut() { rows.push(row); }
+            } else {
+                steps.push_back(Step::Check { line: line, rows: vec![row] });
+                in_check = true;
+            }
+            continue;
+        }
+        in_check = false;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') { continue; }
+        let (command, arg) = match text.find(' ') {
+            Some(n) => (&text[.. n], &text[n + 1 ..]),
+            None => (text, "")
+        };
+        match command {
+            "text" => steps.extend(arg.chars().map(|c| Step::Event(Event::Char(c, Mods::NONE)))),
+            "key" => {
+                for name in arg.split_whitespace() {
+                    steps.push_back(Step::Event(parse_key(name).ok_or_else(&invalid)?));
+                }
+            },
+            "resize" => {
+                let mut size = arg.split_whitespace().map(|n| n.parse::<isize>().ok());
+                match (size.next(), size.next(), size.next()) {
+                    (Some(Some(height)), Some(Some(width)), None) if height > 0 && width > 0 => steps.push_back(Step::Resize(height, width)),
+                    _ => return Err(invalid())
+                }
+            },
+            "delay" => steps.push_back(Step::Delay(Duration::from_millis(arg.parse().map_err(|_| invalid())?))),
+            _ => return Err(invalid())
+        }
+    }
+    Ok(steps)
+}
+
+// Plays an input script to the application and checks the screen at its checkpoints.
+//
+// Script lines are `text <chars>`, `key <name>...` (e.g. `key C-LEFT F5 ESC q`),
+// `resize <height> <width>` and `delay <ms>`; a block of lines starting with `|` is a checkpoint
+// listing the expected screen rows, compared as soon as the application asks for the next input.
+// Empty lines and lines starting with `#` are ignored.
+pub struct Replay {
+    screen: MemScr,
+    steps: VecDeque<Step>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Replay {
+    pub fn new(height: isize, width: isize, script: &str) -> Result<Replay, ScriptError> {
+        Ok(Replay { screen: MemScr::new(height, width), steps: parse(script)?, mismatches: Vec::new() })
+    }
+    pub fn screen(&self) -> &MemScr { &self.screen }
+    pub fn is_finished(&self) -> bool { self.steps.is_empty() }
+    pub fn mismatches(&self) -> &[Mismatch] { &self.mismatches }
+    fn check(&mut self, line: usize, rows: &[String]) {
+        for (y, expected) in rows.iter().enumerate() {
+            let y = y as isize;
+            let actual = if y < self.screen.get_height().unwrap() { self.screen.row_text(y).trim_end().to_string() } else { String::new() };
+            if *expected != actual {
+                self.mismatches.push(Mismatch { line: line + y as usize, row: y, expected: expected.clone(), actual: actual });
+            }
+        }
+    }
+}
+
+impl Scr for Replay {
+    fn get_height(&self) -> Result<isize, Error> { self.screen.get_height() }
+    fn get_width(&self) -> Result<isize, Error> { self.screen.get_width() }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> { self.screen.out(y, x, c) }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> { self.screen.refresh(cursor) }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> { self.screen.set_cursor_shape(shape) }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> { self.screen.set_title(title) }
+    fn beep(&mut self) -> Result<(), Error> { self.screen.beep() }
+    fn flash(&mut self) -> Result<(), Error> { self.screen.flash() }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> { self.screen.set_clipboard(text) }
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> { self.screen.get_clipboard() }
+    fn input_timeout(&self) -> Option<Duration> {
+        match self.steps.front() {
+            Some(&Step::Delay(delay)) => Some(delay),
+            Some(&Step::Until(until)) => {
+                let now = Instant::now();
+                Some(if until > now { until - now } else { Duration::from_millis(0) })
+            },
+            _ => Some(Duration::from_millis(0))
+        }
+    }
+    // Sleeps through a delay no longer than `timeout` allows, and returns `Ok(None)` once it is over,
+    // so that timers due meanwhile fire before the next step.
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        match self.steps.pop_front() {
+            None => Err(Error::InputClosed),
+            Some(Step::Event(e)) => Ok(Some(e)),
+            Some(Step::Resize(height, width)) => {
+                self.screen.push_resize(height, width);
+                self.screen.poll(None)
+            },
+            Some(Step::Delay(delay)) => {
+                self.steps.push_front(Step::Until(Instant::now() + delay));
+                self.poll(timeout)
+            },
+            Some(Step::Until(until)) => {
+                let now = Instant::now();
+                if until > now {
+                    let left = until - now;
+                    let nap = timeout.map_or(left, |t| t.min(left));
+                    sleep(nap);
+                    if nap < left { self.steps.push_front(Step::Until(until)); }
+                }
+                Ok(None)
+            },
+            Some(Step::Check { line, rows }) => {
+                self.check(line, &rows);
+                self.poll(timeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, Instant };
+    use replay::{ Replay, Mismatch, ScriptError };
+    use scr::{ Scr, Attr, Color, Texel, Key, Event, Mods, Error };
+
+    fn echo(r: &mut Replay) -> Result<(), Error> {
+        let mut x = 0;
+        loop {
+            r.refresh(None)?;
+            match r.getch()? {
+                Event::Char(c, Mods::NONE) => {
+                    r.out(0, x, &Texel { ch: c.into(), attr: Attr::NORMAL, fg: Color::White, bg: None })?;
+                    x += 1;
+                },
+                Event::Key(Key::BACKSPACE, _) => {
+                    x -= 1;
+                    r.out(0, x, &Texel { ch: ' '.into(), attr: Attr::NORMAL, fg: Color::White, bg: None })?;
+                },
+                _ => { }
+            }
+        }
+    }
+
+    #[test]
+    fn replays_and_checks() {
+        let script = "
+# typing
+text abc
+key BACKSPACE
+|ab
+|
+delay 20
+key x C-q F5 S-UP
+resize 1 4
+|abx|
+|abc
+";
+        let mut r = Replay::new(2, 5, script).unwrap();
+        let start = Instant::now();
+        assert_eq!(Err(Error::InputClosed), echo(&mut r));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(r.is_finished());
+        assert_eq!(4, r.get_width().unwrap());
+        assert_eq!(&[Mismatch { line: 11, row: 1, expected: "abc".to_string(), actual: String::new() }], r.mismatches());
+        let mut r = Replay::new(1, 1, "delay 20\ntext a").unwrap();
+        assert_eq!(Some(Duration::from_millis(20)), r.input_timeout());
+        assert_eq!(Ok(None), r.poll(Some(Duration::from_millis(0))));
+        assert!(r.input_timeout().unwrap() <= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn script_errors() {
+        assert_eq!(Some(Event::Key(Key::LEFT, Mods::CTRL | Mods::SHIFT)), super::parse_key("C-S-LEFT"));
+        assert_eq!(Some(Event::Char('\x1B', Mods::NONE)), super::parse_key("ESC"));
+        assert_eq!(Some(Event::Char('\t', Mods::SHIFT)), super::parse_key("BTAB"));
+        assert_eq!(Some(Event::Char('\t', Mods::SHIFT | Mods::CTRL)), super::parse_key("C-BTAB"));
+        assert_eq!(Err(ScriptError { line: 2, text: "key NOPE".to_string() }), Replay::new(1, 1, "text a\nkey NOPE").map(|_| ()));
+        assert_eq!(Err(ScriptError { line: 1, text: "resize 0 1".to_string() }), Replay::new(1, 1, "resize 0 1").map(|_| ()));
+        assert_eq!("invalid script line 3: \"key X-Y\"", format!("{}", ScriptError { line: 3, text: String::from("key X-Y") }));
+    }
+}
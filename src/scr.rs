@@ -1,6 +1,12 @@
 #![deny(warnings)]
+use std::error;
+use std::fmt::{ self, Display, Formatter };
+use std::os::raw::c_int;
+use std::sync::Arc;
+use std::time::Duration;
+use unicode_width::{ UnicodeWidthChar, UnicodeWidthStr };
 
-use either::Either;
+use either::{ Either, Left, Right };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Key {
@@ -108,16 +114,118 @@ impl Key {
 
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-#[repr(i8)]
 pub enum Color {
-    Black = 0,
-    Red = 1,
-    Green = 2,
-    Yellow = 3,
-    Blue = 4,
-    Magenta = 5,
-    Cyan = 6,
-    White = 7,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const SYSTEM_COLORS: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan, Color::White,
+    Color::BrightBlack, Color::BrightRed, Color::BrightGreen, Color::BrightYellow,
+    Color::BrightBlue, Color::BrightMagenta, Color::BrightCyan, Color::BrightWhite,
+];
+
+const SYSTEM_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| { let d = x as i32 - y as i32; (d * d) as u32 };
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+impl Color {
+    pub fn index(self) -> Option<u8> {
+        match self {
+            Color::Indexed(n) => Some(n),
+            Color::Rgb(_, _, _) => None,
+            c => SYSTEM_COLORS.iter().position(|&b| b == c).map(|i| i as u8)
+        }
+    }
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            c => {
+                let n = c.index().unwrap();
+                if n < 16 {
+                    SYSTEM_RGB[n as usize]
+                } else if n < 232 {
+                    let n = n - 16;
+                    (CUBE_LEVELS[(n / 36) as usize], CUBE_LEVELS[(n / 6 % 6) as usize], CUBE_LEVELS[(n % 6) as usize])
+                } else {
+                    let l = 8 + 10 * (n - 232);
+                    (l, l, l)
+                }
+            }
+        }
+    }
+    pub fn to_256(self) -> Color {
+        match self {
+            Color::Rgb(r, g, b) => {
+                fn level(v: u8) -> usize {
+                    CUBE_LEVELS.iter().enumerate().min_by_key(|&(_, &l)| (l as i32 - v as i32).abs()).unwrap().0
+                }
+                let cube_index = (level(r), level(g), level(b));
+                let cube = (CUBE_LEVELS[cube_index.0], CUBE_LEVELS[cube_index.1], CUBE_LEVELS[cube_index.2]);
+                let gray_index = (((r as u32 + g as u32 + b as u32) / 3).saturating_sub(3) / 10).min(23) as u8;
+                let gray = 8 + 10 * gray_index;
+                if rgb_distance((r, g, b), (gray, gray, gray)) < rgb_distance((r, g, b), cube) {
+                    Color::Indexed(232 + gray_index)
+                } else {
+                    Color::Indexed(16 + 36 * cube_index.0 as u8 + 6 * cube_index.1 as u8 + cube_index.2 as u8)
+                }
+            },
+            c => c
+        }
+    }
+    pub fn is_bright(self) -> bool {
+        match self.index() {
+            Some(n) => n >= 8 && n < 16,
+            None => false
+        }
+    }
+    pub fn to_16(self) -> Color {
+        match self.index() {
+            Some(n) if n < 16 => SYSTEM_COLORS[n as usize],
+            _ => {
+                let rgb = self.rgb();
+                *SYSTEM_COLORS.iter().min_by_key(|c| rgb_distance(c.rgb(), rgb)).unwrap()
+            }
+        }
+    }
+    pub fn to_8(self) -> Color {
+        SYSTEM_COLORS[(self.to_16().index().unwrap() % 8) as usize]
+    }
+    pub fn approx(self, colors: u32) -> Color {
+        if colors >= 1 << 24 {
+            self
+        } else if colors >= 256 {
+            self.to_256()
+        } else if colors >= 16 {
+            self.to_16()
+        } else {
+            self.to_8()
+        }
+    }
 }
 
 bitflags! {
@@ -141,27 +249,327 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct Mods: u8 {
+        const NONE = 0;
+        const SHIFT = 1 << 0;
+        const ALT = 1 << 1;
+        const CTRL = 1 << 2;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MouseAction {
+    Press,
+    Release,
+    Drag,
+    Move,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MouseEvent {
+    pub y: isize,
+    pub x: isize,
+    pub button: Option<MouseButton>,
+    pub action: MouseAction,
+    pub mods: Mods,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Event {
+    Key(Key, Mods),
+    Char(char, Mods),
+    Mouse(MouseEvent),
+}
+
+const SHIFTED_KEYS: [(Key, Key); 30] = [
+    (Key::SBEG, Key::BEG), (Key::SCANCEL, Key::CANCEL), (Key::SCOMMAND, Key::COMMAND), (Key::SCOPY, Key::COPY),
+    (Key::SCREATE, Key::CREATE), (Key::SDC, Key::DC), (Key::SDL, Key::DL), (Key::SEND, Key::END),
+    (Key::SEOL, Key::EOL), (Key::SEXIT, Key::EXIT), (Key::SFIND, Key::FIND), (Key::SHELP, Key::HELP),
+    (Key::SHOME, Key::HOME), (Key::SIC, Key::IC), (Key::SLEFT, Key::LEFT), (Key::SMESSAGE, Key::MESSAGE),
+    (Key::SMOVE, Key::MOVE), (Key::SNEXT, Key::NEXT), (Key::SOPTIONS, Key::OPTIONS), (Key::SPREVIOUS, Key::PREVIOUS),
+    (Key::SPRINT, Key::PRINT), (Key::SREDO, Key::REDO), (Key::SREPLACE, Key::REPLACE), (Key::SRIGHT, Key::RIGHT),
+    (Key::SRSUME, Key::RESUME), (Key::SSAVE, Key::SAVE), (Key::SSUSPEND, Key::SUSPEND), (Key::SUNDO, Key::UNDO),
+    (Key::SF, Key::DOWN), (Key::SR, Key::UP),
+];
+
+impl Event {
+    pub fn decode_char(c: char) -> Event {
+        match c {
+            '\r' | '\n' => Event::Char('\n', Mods::NONE),
+            '\t' | '\x1B' => Event::Char(c, Mods::NONE),
+            '\0' => Event::Char(' ', Mods::CTRL),
+            '\x01' ..= '\x1A' => Event::Char((c as u8 - 1 + b'a') as char, Mods::CTRL),
+            '\x1C' ..= '\x1F' => Event::Char((c as u8 - 0x1C + b'\\') as char, Mods::CTRL),
+            c => Event::Char(c, Mods::NONE)
+        }
+    }
+    pub fn mods(&self) -> Mods {
+        match *self {
+            Event::Key(_, mods) => mods,
+            Event::Char(_, mods) => mods,
+            Event::Mouse(ref e) => e.mods,
+        }
+    }
+    pub fn with_mods(self, mods: Mods) -> Event {
+        match self {
+            Event::Key(key, m) => Event::Key(key, m | mods),
+            Event::Char(c, m) => Event::Char(c, m | mods),
+            Event::Mouse(e) => Event::Mouse(MouseEvent { mods: e.mods | mods, ..e }),
+        }
+    }
+    pub fn to_legacy(&self) -> Option<Either<Key, char>> {
+        match *self {
+            Event::Key(key, Mods::NONE) => Some(Left(key)),
+            Event::Key(key, Mods::SHIFT) => {
+                SHIFTED_KEYS.iter().filter(|&&(_, k)| k == key).next().map(|&(shifted, _)| Left(shifted))
+            },
+            Event::Char('\t', Mods::SHIFT) => Some(Left(Key::BTAB)),
+            Event::Char(c, Mods::NONE) => Some(Right(c)),
+            Event::Char(c, Mods::CTRL) => match c {
+                ' ' => Some(Right('\0')),
+                'a' ..= 'z' => Some(Right((c as u8 - b'a' + 1) as char)),
+                '\\' ..= '_' => Some(Right((c as u8 - b'\\' + 0x1C) as char)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+impl From<Key> for Event {
+    fn from(key: Key) -> Event {
+        if key == Key::BTAB { return Event::Char('\t', Mods::SHIFT); }
+        match SHIFTED_KEYS.iter().filter(|&&(shifted, _)| shifted == key).next() {
+            Some(&(_, key)) => Event::Key(key, Mods::SHIFT),
+            None => Event::Key(key, Mods::NONE)
+        }
+    }
+}
+
+impl From<char> for Event {
+    fn from(c: char) -> Event { Event::decode_char(c) }
+}
+
+impl From<Either<Key, char>> for Event {
+    fn from(e: Either<Key, char>) -> Event {
+        match e {
+            Left(key) => Event::from(key),
+            Right(c) => Event::from(c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum GraphemeRepr {
+    Char(char),
+    Cluster(Arc<str>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grapheme(GraphemeRepr);
+
+impl Grapheme {
+    pub fn new(s: &str) -> Grapheme {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => Grapheme(GraphemeRepr::Char(' ')),
+            (Some(c), None) => Grapheme(GraphemeRepr::Char(c)),
+            _ => Grapheme(GraphemeRepr::Cluster(Arc::from(s))),
+        }
+    }
+    pub fn base(&self) -> char {
+        match self.0 {
+            GraphemeRepr::Char(c) => c,
+            GraphemeRepr::Cluster(ref s) => s.chars().next().unwrap(),
+        }
+    }
+    // Cells the cluster takes: its `UnicodeWidthStr` width (which knows emoji presentation and
+    // ZWJ sequences), clamped to 1 ..= 2 since a cluster always gets one cell and never more than two.
+    pub fn width(&self) -> isize {
+        let width = match self.0 {
+            GraphemeRepr::Char(c) => c.width().unwrap_or(0),
+            GraphemeRepr::Cluster(ref s) => s.width(),
+        };
+        width.max(1).min(2) as isize
+    }
+}
+
+impl From<char> for Grapheme {
+    fn from(c: char) -> Grapheme { Grapheme(GraphemeRepr::Char(c)) }
+}
+
+impl<'a> From<&'a str> for Grapheme {
+    fn from(s: &'a str) -> Grapheme { Grapheme::new(s) }
+}
+
+impl PartialEq<char> for Grapheme {
+    fn eq(&self, other: &char) -> bool { self.0 == GraphemeRepr::Char(*other) }
+}
+
+impl PartialEq<Grapheme> for char {
+    fn eq(&self, other: &Grapheme) -> bool { other == self }
+}
+
+impl Display for Grapheme {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            GraphemeRepr::Char(c) => write!(f, "{}", c),
+            GraphemeRepr::Cluster(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Texel {
-    pub ch: char,
+    pub ch: Grapheme,
     pub attr: Attr,
     pub fg: Color,
     pub bg: Option<Color>,
 }
 
+impl Texel {
+    pub fn width(&self) -> isize {
+        if self.attr.contains(Attr::ALTCHARSET) || self.is_tail() { return 1; }
+        self.ch.width()
+    }
+    // The second cell of a double-width texel.
+    pub fn tail(&self) -> Texel {
+        Texel { ch: '\0'.into(), ..self.clone() }
+    }
+    pub fn is_tail(&self) -> bool { self.ch == '\0' }
+    pub fn narrow(&self) -> Texel {
+        if self.width() > 1 { Texel { ch: ' '.into(), ..self.clone() } } else { self.clone() }
+    }
+}
+
+// Discriminants are the DECSCUSR parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CursorShape {
+    Default = 0,
+    BlinkingBlock = 1,
+    Block = 2,
+    BlinkingUnderline = 3,
+    Underline = 4,
+    BlinkingBar = 5,
+    Bar = 6,
+}
+
+impl Default for CursorShape {
+    fn default() -> CursorShape { CursorShape::Default }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Curses { call: &'static str, context: String },
+    Os { call: &'static str, errno: i32 },
+    InvalidUtf8(Vec<u8>),
+    UnknownMouseEvent,
+    OutOfBounds { y: isize, x: isize },
+    InputClosed,
+    NoInput,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Curses { call, ref context } if context.is_empty() => write!(f, "{} failed", call),
+            Error::Curses { call, ref context } => write!(f, "{} failed ({})", call, context),
+            Error::Os { call, errno } => write!(f, "{} failed (errno {})", call, errno),
+            Error::InvalidUtf8(ref bytes) => write!(f, "invalid UTF-8 input {:02X?}", bytes),
+            Error::UnknownMouseEvent => write!(f, "unknown mouse event"),
+            Error::OutOfBounds { y, x } => write!(f, "position (y = {}, x = {}) is out of screen bounds", y, x),
+            Error::InputClosed => write!(f, "input closed"),
+            Error::NoInput => write!(f, "no input"),
+        }
+    }
+}
+
+impl error::Error for Error { }
+
 pub trait Scr {
-    fn get_height(&self) -> Result<isize, ()>;
-    fn get_width(&self) -> Result<isize, ()>;
-    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), ()>;
-    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), ()>;
-    fn getch(&mut self) -> Result<Either<Key, char>, ()>;
+    fn get_height(&self) -> Result<isize, Error>;
+    fn get_width(&self) -> Result<isize, Error>;
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error>;
+    // Outputs consecutive cells of a row, tails of double-width texels included.
+    fn out_run(&mut self, y: isize, x: isize, texels: &[Texel]) -> Result<(), Error> {
+        for (i, t) in texels.iter().enumerate() {
+            self.out(y, x + i as isize, t)?;
+        }
+        Ok(())
+    }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error>;
+    // Takes effect on the next refresh; terminals unable to change the shape ignore it.
+    fn set_cursor_shape(&mut self, _shape: CursorShape) -> Result<(), Error> { Ok(()) }
+    // The original title comes back on suspend and when the screen is dropped.
+    fn set_title(&mut self, _title: &str) -> Result<(), Error> { Ok(()) }
+    fn beep(&mut self) -> Result<(), Error> { Ok(()) }
+    fn flash(&mut self) -> Result<(), Error> { Ok(()) }
+    // Puts `text` on the system clipboard, if the terminal lets it.
+    fn set_clipboard(&mut self, _text: &str) -> Result<(), Error> { Ok(()) }
+    // Asks the terminal for the system clipboard; `None` if it does not tell. Blocks until it answers or times out.
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> { Ok(None) }
+    // Waits at most `timeout` (forever if `None`) and returns `Ok(None)` if no input arrived.
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error>;
+    fn getch(&mut self) -> Result<Event, Error> {
+        loop {
+            if let Some(e) = self.poll(None)? { return Ok(e); }
+        }
+    }
+    fn set_esc_delay(&mut self, _ms: u32) { }
+    fn input_fd(&self) -> Option<c_int> { None }
+    // The input and output descriptors of the terminal, for the programs `Ntfl::run_external` starts.
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> { None }
+    // Becomes readable when a signal leaves an event for `poll` (e.g. a resize).
+    fn signal_fd(&self) -> Option<c_int> { None }
+    // How soon `poll` may have something that no descriptor announces (e.g. a scripted delay ending).
+    fn input_timeout(&self) -> Option<Duration> { None }
+    // Gives the terminal back (e.g. to a child process or the shell) until `resume`.
+    fn suspend(&mut self) -> Result<(), Error> { Ok(()) }
+    // Takes the terminal again; its content is lost, so callers repaint everything.
+    fn resume(&mut self) -> Result<(), Error> { Ok(()) }
+}
+
+impl<'a, S : Scr + ?Sized> Scr for &'a mut S {
+    fn get_height(&self) -> Result<isize, Error> { (**self).get_height() }
+    fn get_width(&self) -> Result<isize, Error> { (**self).get_width() }
+    fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> { (**self).out(y, x, c) }
+    fn out_run(&mut self, y: isize, x: isize, texels: &[Texel]) -> Result<(), Error> { (**self).out_run(y, x, texels) }
+    fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> { (**self).refresh(cursor) }
+    fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> { (**self).set_cursor_shape(shape) }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> { (**self).set_title(title) }
+    fn beep(&mut self) -> Result<(), Error> { (**self).beep() }
+    fn flash(&mut self) -> Result<(), Error> { (**self).flash() }
+    fn set_clipboard(&mut self, text: &str) -> Result<(), Error> { (**self).set_clipboard(text) }
+    fn get_clipboard(&mut self) -> Result<Option<String>, Error> { (**self).get_clipboard() }
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> { (**self).poll(timeout) }
+    fn getch(&mut self) -> Result<Event, Error> { (**self).getch() }
+    fn set_esc_delay(&mut self, ms: u32) { (**self).set_esc_delay(ms) }
+    fn input_fd(&self) -> Option<c_int> { (**self).input_fd() }
+    fn terminal_fds(&self) -> Option<(c_int, c_int)> { (**self).terminal_fds() }
+    fn signal_fd(&self) -> Option<c_int> { (**self).signal_fd() }
+    fn input_timeout(&self) -> Option<Duration> { (**self).input_timeout() }
+    fn suspend(&mut self) -> Result<(), Error> { (**self).suspend() }
+    fn resume(&mut self) -> Result<(), Error> { (**self).resume() }
 }
 
 #[cfg(test)]
 pub mod tests {
     use std::mem::replace;
-    use either::Either;
-    use scr::{ Attr, Texel, Color, Scr, Key };
+    use std::time::Duration;
+    use either::{ Left, Right };
+    use scr::{ Attr, Texel, Color, Scr, Key, Event, Mods, Error, Grapheme, CursorShape };
 
     pub struct TestScr {
         pub height: isize,
@@ -169,6 +577,8 @@ pub mod tests {
         pub invalid: bool,
         pub content: Vec<Texel>,
         pub cursor: Option<(isize, isize)>,
+        pub cursor_shape: CursorShape,
+        pub runs: Vec<(isize, isize, usize)>,
     }
     impl TestScr {
         pub fn new(height: isize, width: isize) -> TestScr {
@@ -176,8 +586,10 @@ pub mod tests {
                 height: height,
                 width: width,
                 invalid: false,
-                content: vec![Texel { ch: 'T', attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) }; (height * width) as usize],
-                cursor: None
+                content: vec![Texel { ch: 'T'.into(), attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) }; (height * width) as usize],
+                cursor: None,
+                cursor_shape: CursorShape::Default,
+                runs: Vec::new()
             }
         }
         pub fn content(&self, y: isize, x: isize) -> &Texel {
@@ -185,20 +597,115 @@ pub mod tests {
         }
     }
     impl Scr for TestScr {
-        fn get_height(&self) -> Result<isize, ()> { Ok(self.height) }
-        fn get_width(&self) -> Result<isize, ()> { Ok(self.width) }
-        fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), ()> {
+        fn get_height(&self) -> Result<isize, Error> { Ok(self.height) }
+        fn get_width(&self) -> Result<isize, Error> { Ok(self.width) }
+        fn out(&mut self, y: isize, x: isize, c: &Texel) -> Result<(), Error> {
             self.invalid = true;
             replace(&mut self.content[(y * self.width + x) as usize], c.clone());
             Ok(())
         }
-        fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), ()> {
+        fn out_run(&mut self, y: isize, x: isize, texels: &[Texel]) -> Result<(), Error> {
+            self.runs.push((y, x, texels.len()));
+            for (i, t) in texels.iter().enumerate() {
+                self.out(y, x + i as isize, t)?;
+            }
+            Ok(())
+        }
+        fn refresh(&mut self, cursor: Option<(isize, isize)>) -> Result<(), Error> {
             self.invalid = false;
             self.cursor = cursor;
             Ok(())
         }
-        fn getch(&mut self) -> Result<Either<Key, char>, ()> {
-            Err(())
+        fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<(), Error> {
+            self.cursor_shape = shape;
+            Ok(())
+        }
+        fn poll(&mut self, _timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+            Ok(None)
+        }
+        fn getch(&mut self) -> Result<Event, Error> {
+            Err(Error::NoInput)
+        }
+    }
+
+    #[test]
+    fn event_from_legacy() {
+        assert_eq!(Event::Key(Key::UP, Mods::NONE), Event::from(Key::UP));
+        assert_eq!(Event::Key(Key::LEFT, Mods::SHIFT), Event::from(Key::SLEFT));
+        assert_eq!(Event::Char('\t', Mods::SHIFT), Event::from(Key::BTAB));
+        assert_eq!(Event::Char('c', Mods::CTRL), Event::from('\x03'));
+        assert_eq!(Event::Char(']', Mods::CTRL), Event::from('\x1D'));
+        assert_eq!(Event::Char('\n', Mods::NONE), Event::from(Right('\r')));
+        assert_eq!(Event::Char('ы', Mods::NONE), Event::from(Right('ы')));
+    }
+
+    #[test]
+    fn event_to_legacy_roundtrip() {
+        for value in Key::MIN.value .. Key::EVENT.value + 1 {
+            let key = Key { value: value };
+            assert_eq!(Some(Left(key)), Event::from(key).to_legacy());
         }
+        for c in (0 .. 0x80).filter_map(::std::char::from_u32).filter(|&c| c != '\r') {
+            assert_eq!(Some(Right(c)), Event::from(c).to_legacy());
+        }
+        assert_eq!(None, Event::Key(Key::UP, Mods::CTRL).to_legacy());
+        assert_eq!(Event::Char('x', Mods::ALT | Mods::SHIFT), Event::Char('x', Mods::ALT).with_mods(Mods::SHIFT));
+    }
+
+    #[test]
+    fn color_palette() {
+        assert_eq!(Some(3), Color::Yellow.index());
+        assert_eq!((0, 0, 0), Color::Indexed(16).rgb());
+        assert_eq!((255, 255, 255), Color::Indexed(231).rgb());
+        assert_eq!((238, 238, 238), Color::Indexed(255).rgb());
+        assert_eq!(Color::Indexed(196), Color::Rgb(250, 10, 0).to_256());
+        assert_eq!(Color::Indexed(244), Color::Rgb(128, 128, 128).to_256());
+        assert_eq!(Color::Green, Color::Green.to_256());
+    }
+
+    #[test]
+    fn color_approx() {
+        assert_eq!(Color::Rgb(1, 2, 3), Color::Rgb(1, 2, 3).approx(1 << 24));
+        assert_eq!(Color::Indexed(21), Color::Rgb(0, 0, 255).approx(256));
+        assert_eq!(Color::Blue, Color::Rgb(0, 0, 200).approx(8));
+        assert_eq!(Color::Red, Color::Indexed(9).approx(8));
+        assert_eq!(Color::White, Color::Indexed(252).approx(8));
+    }
+
+    #[test]
+    fn bright_colors() {
+        assert_eq!(Some(9), Color::BrightRed.index());
+        assert_eq!((255, 255, 255), Color::BrightWhite.rgb());
+        assert!(Color::BrightBlue.is_bright());
+        assert!(Color::Indexed(12).is_bright());
+        assert!(!Color::Blue.is_bright());
+        assert_eq!(Color::BrightCyan, Color::Indexed(14).approx(16));
+        assert_eq!(Color::BrightYellow, Color::Rgb(250, 250, 10).approx(16));
+        assert_eq!(Color::Cyan, Color::BrightCyan.approx(8));
+        assert_eq!(Color::BrightGreen, Color::BrightGreen.approx(256));
+    }
+
+    #[test]
+    fn error_display() {
+        assert_eq!("newterm failed", format!("{}", Error::Curses { call: "newterm", context: String::new() }));
+        assert_eq!("wmove failed (y = 1, x = 2)", format!("{}", Error::Curses { call: "wmove", context: String::from("y = 1, x = 2") }));
+        assert_eq!("invalid UTF-8 input [FF, 61]", format!("{}", Error::InvalidUtf8(vec![0xFF, 0x61])));
+    }
+
+    #[test]
+    fn graphemes() {
+        assert_eq!('a', Grapheme::from("a"));
+        assert_eq!(Grapheme::from('a'), Grapheme::new("a"));
+        assert_eq!("e\u{301}", Grapheme::new("e\u{301}").to_string());
+        assert_eq!('e', Grapheme::new("e\u{301}").base());
+        assert_eq!(1, Grapheme::new("e\u{301}").width());
+        assert_eq!(2, Grapheme::new("日").width());
+        assert_eq!(2, Grapheme::new("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}").width());
+        assert_eq!(2, Grapheme::new("\u{2764}\u{FE0F}").width());
+        assert_eq!(1, Grapheme::new("\u{2764}").width());
+        assert_eq!(2, Grapheme::new("\u{1F1FA}\u{1F1F8}").width());
+        assert_eq!(2, Grapheme::new("\u{1F44D}\u{1F3FD}").width());
+        assert_eq!(1, Grapheme::new("\u{301}").width());
+        assert_eq!(1, Grapheme::new("\u{7}").width());
     }
 }
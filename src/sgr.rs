@@ -0,0 +1,251 @@
+#![deny(warnings)]
+#[cfg(feature = "ansi")]
+use std::env;
+use std::io::Write;
+
+use scr::{ Attr, Color, Key, Event, Mods, MouseAction, MouseButton };
+
+pub fn acs(ch: char) -> char {
+    match ch {
+        'l' => '┌',
+        'k' => '┐',
+        'm' => '└',
+        'j' => '┘',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'q' => '─',
+        'x' => '│',
+        'n' => '┼',
+        'o' => '⎺',
+        's' => '⎽',
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        '~' => '·',
+        ',' => '←',
+        '+' => '→',
+        '.' => '↓',
+        '-' => '↑',
+        'h' => '░',
+        'i' => '␋',
+        '0' => '█',
+        'p' => '⎻',
+        'r' => '⎼',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        c => c
+    }
+}
+
+pub fn write_sgr(buf: &mut Vec<u8>, attr: Attr, fg: Color, bg: Option<Color>) {
+    buf.extend_from_slice(b"\x1b[0");
+    if attr.contains(Attr::BOLD) { buf.extend_from_slice(b";1"); }
+    if attr.contains(Attr::DIM) { buf.extend_from_slice(b";2"); }
+    if attr.contains(Attr::UNDERLINE) { buf.extend_from_slice(b";4"); }
+    if attr.contains(Attr::BLINK) { buf.extend_from_slice(b";5"); }
+    if attr.intersects(Attr::REVERSE | Attr::STANDOUT) { buf.extend_from_slice(b";7"); }
+    if attr.contains(Attr::INVIS) { buf.extend_from_slice(b";8"); }
+    write_color(buf, 30, fg);
+    match bg {
+        Some(bg) => write_color(buf, 40, bg),
+        None => buf.extend_from_slice(b";49")
+    }
+    buf.push(b'm');
+}
+
+fn write_color(buf: &mut Vec<u8>, base: u8, c: Color) {
+    match c {
+        Color::Rgb(r, g, b) => write!(buf, ";{};2;{};{};{}", base + 8, r, g, b).unwrap(),
+        Color::Indexed(n) => write!(buf, ";{};5;{}", base + 8, n).unwrap(),
+        c if c.is_bright() => write!(buf, ";{}", base + 60 + c.index().unwrap() - 8).unwrap(),
+        c => write!(buf, ";{}", base + c.index().unwrap()).unwrap()
+    }
+}
+
+// Encodes an event the way an xterm sends it; returns false for events it has no sequence for.
+pub fn write_input(buf: &mut Vec<u8>, e: &Event) -> bool {
+    match *e {
+        Event::Char('\t', mods) if mods.contains(Mods::SHIFT) => {
+            buf.extend_from_slice(b"\x1b[Z");
+        },
+        Event::Char(c, mods) => {
+            if mods.contains(Mods::ALT) { buf.push(0x1B); }
+            let c = match c {
+                '\n' => '\r',
+                ' ' if mods.contains(Mods::CTRL) => '\0',
+                'a' ..= 'z' if mods.contains(Mods::CTRL) => (c as u8 - b'a' + 1) as char,
+                '\\' ..= '_' if mods.contains(Mods::CTRL) => (c as u8 - b'\\' + 0x1C) as char,
+                c => c
+            };
+            let mut b = [0; 4];
+            buf.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+        },
+        Event::Key(key, mods) => {
+            let m = if mods == Mods::NONE { None } else { Some(mods.bits() + 1) };
+            let letter = match key {
+                Key::UP => Some(b'A'),
+                Key::DOWN => Some(b'B'),
+                Key::RIGHT => Some(b'C'),
+                Key::LEFT => Some(b'D'),
+                Key::B2 => Some(b'E'),
+                Key::END => Some(b'F'),
+                Key::HOME => Some(b'H'),
+                k if k >= Key::f(1) && k <= Key::f(4) => Some(b'P' + (k.value - Key::F0.value - 1) as u8),
+                _ => None
+            };
+            let tilde = match key {
+                Key::IC => Some(2),
+                Key::DC => Some(3),
+                Key::PPAGE => Some(5),
+                Key::NPAGE => Some(6),
+                k if k == Key::f(5) => Some(15),
+                k if k >= Key::f(6) && k <= Key::f(10) => Some(k.value - Key::F0.value + 11),
+                k if k >= Key::f(11) && k <= Key::f(12) => Some(k.value - Key::F0.value + 12),
+                _ => None
+            };
+            match (letter, tilde, m) {
+                (Some(c), _, None) if c >= b'P' => { buf.extend_from_slice(b"\x1bO"); buf.push(c); },
+                (Some(c), _, None) => { buf.extend_from_slice(b"\x1b["); buf.push(c); },
+                (Some(c), _, Some(m)) => { write!(buf, "\x1b[1;{}", m).unwrap(); buf.push(c); },
+                (None, Some(n), None) => write!(buf, "\x1b[{}~", n).unwrap(),
+                (None, Some(n), Some(m)) => write!(buf, "\x1b[{};{}~", n, m).unwrap(),
+                (None, None, None) if key == Key::BACKSPACE => buf.push(0x7F),
+                (None, None, None) if key == Key::ENTER => buf.extend_from_slice(b"\x1bOM"),
+                _ => return false
+            }
+        },
+        Event::Mouse(ref e) => {
+            let mut cb = match e.button {
+                Some(MouseButton::Left) => 0,
+                Some(MouseButton::Middle) => 1,
+                Some(MouseButton::Right) => 2,
+                Some(MouseButton::WheelUp) => 64,
+                Some(MouseButton::WheelDown) => 65,
+                Some(MouseButton::WheelLeft) => 66,
+                Some(MouseButton::WheelRight) => 67,
+                None => 3
+            };
+            if e.mods.contains(Mods::SHIFT) { cb |= 4; }
+            if e.mods.contains(Mods::ALT) { cb |= 8; }
+            if e.mods.contains(Mods::CTRL) { cb |= 16; }
+            if e.action == MouseAction::Drag || e.action == MouseAction::Move { cb |= 32; }
+            let final_ = if e.action == MouseAction::Release { 'm' } else { 'M' };
+            write!(buf, "\x1b[<{};{};{}{}", cb, e.x + 1, e.y + 1, final_).unwrap();
+        }
+    }
+    true
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// OSC 52 for the clipboard selection, the text in base64.
+pub fn write_clipboard(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(b"\x1b]52;c;");
+    for chunk in text.as_bytes().chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0 .. 4 {
+            buf.push(if i <= chunk.len() { BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] } else { b'=' });
+        }
+    }
+    buf.push(0x07);
+}
+
+#[cfg(feature = "ansi")]
+pub fn decode_base64(s: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+    for &c in s.iter().take_while(|&&c| c != b'=') {
+        n = n << 6 | BASE64.iter().position(|&b| b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+// OSC 0; control characters would end the sequence early, so they are dropped.
+pub fn write_title(buf: &mut Vec<u8>, title: &str) {
+    buf.extend_from_slice(b"\x1b]0;");
+    for c in title.chars().filter(|c| !c.is_control()) {
+        let mut b = [0; 4];
+        buf.extend_from_slice(c.encode_utf8(&mut b).as_bytes());
+    }
+    buf.push(0x07);
+}
+
+#[cfg(feature = "ansi")]
+pub fn term_colors(term: Option<&str>) -> u32 {
+    let truecolor = env::var("COLORTERM").map(|v| v == "truecolor" || v == "24bit").unwrap_or(false);
+    if truecolor { return 1 << 24; }
+    match term.map(|t| t.to_string()).ok_or(()).or_else(|()| env::var("TERM")) {
+        Ok(ref t) if t.ends_with("256color") => 256,
+        Ok(ref t) if t.ends_with("16color") => 16,
+        _ => 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scr::{ Attr, Color, Key, Event, Mods };
+    use sgr::{ acs, write_sgr, write_input, write_title, write_clipboard };
+
+    #[test]
+    fn sgr_attrs_and_colors() {
+        let mut buf = Vec::new();
+        write_sgr(&mut buf, Attr::BOLD | Attr::UNDERLINE, Color::Green, Some(Color::Blue));
+        assert_eq!(&b"\x1b[0;1;4;32;44m"[..], &buf[..]);
+        buf.clear();
+        write_sgr(&mut buf, Attr::NORMAL, Color::White, None);
+        assert_eq!(&b"\x1b[0;37;49m"[..], &buf[..]);
+        buf.clear();
+        write_sgr(&mut buf, Attr::NORMAL, Color::Indexed(208), Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(&b"\x1b[0;38;5;208;48;2;1;2;3m"[..], &buf[..]);
+        buf.clear();
+        write_sgr(&mut buf, Attr::NORMAL, Color::BrightRed, Some(Color::BrightBlack));
+        assert_eq!(&b"\x1b[0;91;100m"[..], &buf[..]);
+    }
+
+    #[test]
+    fn input_sequences() {
+        let mut buf = Vec::new();
+        assert!(write_input(&mut buf, &Event::Char('c', Mods::CTRL | Mods::ALT)));
+        assert!(write_input(&mut buf, &Event::Key(Key::UP, Mods::NONE)));
+        assert!(write_input(&mut buf, &Event::Key(Key::f(2), Mods::SHIFT)));
+        assert!(write_input(&mut buf, &Event::Key(Key::f(12), Mods::NONE)));
+        assert!(!write_input(&mut buf, &Event::Key(Key::RESIZE, Mods::NONE)));
+        assert_eq!(&b"\x1b\x03\x1b[A\x1b[1;2Q\x1b[24~"[..], &buf[..]);
+    }
+
+    #[test]
+    fn title_sequence() {
+        let mut buf = Vec::new();
+        write_title(&mut buf, "vi \x1b]2;x\x07 — ~/a\n");
+        assert_eq!("\x1b]0;vi ]2;x — ~/a\x07", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn clipboard_sequence() {
+        let mut buf = Vec::new();
+        write_clipboard(&mut buf, "ab");
+        write_clipboard(&mut buf, "abc");
+        write_clipboard(&mut buf, "ы");
+        assert_eq!("\x1b]52;c;YWI=\x07\x1b]52;c;YWJj\x07\x1b]52;c;0Ys=\x07", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn acs_box_drawing() {
+        assert_eq!('┌', acs('l'));
+        assert_eq!('─', acs('q'));
+        assert_eq!('A', acs('A'));
+    }
+}
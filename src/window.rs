@@ -6,7 +6,7 @@ use std::ops::DerefMut;
 use std::sync::{ Arc, Mutex };
 use owning_ref::MutexGuardRef;
 
-use scr::{ Attr, Color, Scr, Texel };
+use scr::{ Attr, Color, Scr, Texel, Error, CursorShape };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct RectValue {
@@ -66,8 +66,8 @@ impl Rect {
     }
     pub fn include(&mut self, y: isize, x: isize) {
         if let Some(ref mut val) = self.val {
-            if y <= val.top { val.top = y; } else { val.height = max(val.height, y + 1 - val.top); }
-            if x <= val.left { val.left = x; } else { val.width = max(val.width, x + 1 - val.left); }
+            if y < val.top { val.height += val.top - y; val.top = y; } else { val.height = max(val.height, y + 1 - val.top); }
+            if x < val.left { val.width += val.left - x; val.left = x; } else { val.width = max(val.width, x + 1 - val.left); }
         } else {
             self.val = Some(RectValue { top: y, left: x, height: 1, width: 1 });
         }
@@ -134,14 +134,118 @@ impl Rect {
         }
         None
     }
+    pub fn subtract(&self, r: &Rect) -> Vec<Rect> {
+        let val = match self.val {
+            None => return Vec::new(),
+            Some(ref val) => val,
+        };
+        let i = match self.inters_rect(r).val {
+            None => return vec![self.clone()],
+            Some(i) => i,
+        };
+        vec![
+            Rect::tlbr(val.top, val.left, i.top, val.right()),
+            Rect::tlbr(i.bottom(), val.left, val.bottom(), val.right()),
+            Rect::tlbr(i.top, val.left, i.bottom(), i.left),
+            Rect::tlbr(i.top, i.right(), i.bottom(), val.right()),
+        ].into_iter().filter(|r| r.val.is_some()).collect()
+    }
+}
+
+// A set of cells kept as disjoint non-empty rectangles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    rects: Vec<Rect>,
+}
+
+impl From<Rect> for Region {
+    fn from(r: Rect) -> Region {
+        Region { rects: if r.val.is_some() { vec![r] } else { Vec::new() } }
+    }
+}
+
+impl Region {
+    pub fn empty() -> Region { Region { rects: Vec::new() } }
+    pub fn is_empty(&self) -> bool { self.rects.is_empty() }
+    pub fn rects(&self) -> &[Rect] { &self.rects }
+    pub fn bounds(&self) -> Rect {
+        let mut bounds = Rect::empty();
+        for r in &self.rects {
+            bounds.union(r.clone());
+        }
+        bounds
+    }
+    pub fn contains(&self, y: isize, x: isize) -> bool {
+        self.rects.iter().any(|r| r.contains(y, x))
+    }
+    pub fn include(&mut self, y: isize, x: isize) {
+        self.union_rect(Rect::tlhw(y, x, 1, 1));
+    }
+    pub fn offset(&mut self, dy: isize, dx: isize) {
+        for r in &mut self.rects {
+            r.offset(dy, dx);
+        }
+    }
+    pub fn union_rect(&mut self, r: Rect) {
+        let mut pieces = Region::from(r).rects;
+        for e in &self.rects {
+            pieces = pieces.iter().flat_map(|p| p.subtract(e)).collect();
+        }
+        for p in pieces {
+            self.insert(p);
+        }
+    }
+    pub fn union(&mut self, r: &Region) {
+        for r in &r.rects {
+            self.union_rect(r.clone());
+        }
+    }
+    pub fn inters_rect(&self, r: &Rect) -> Region {
+        Region { rects: self.rects.iter().map(|e| e.inters_rect(r)).filter(|e| e.val.is_some()).collect() }
+    }
+    pub fn inters(&self, r: &Region) -> Region {
+        let mut rects = Vec::new();
+        for r in &r.rects {
+            rects.extend(self.inters_rect(r).rects);
+        }
+        Region { rects: rects }
+    }
+    pub fn subtract_rect(&mut self, r: &Rect) {
+        self.rects = self.rects.iter().flat_map(|e| e.subtract(r)).collect();
+    }
+    pub fn subtract(&mut self, r: &Region) {
+        for r in &r.rects {
+            self.subtract_rect(r);
+        }
+    }
+    // Adds a rectangle disjoint from the others, merging it with a neighbour sharing a whole edge.
+    fn insert(&mut self, mut r: Rect) {
+        loop {
+            let merged = {
+                let p = r.val.as_ref().unwrap();
+                self.rects.iter().position(|q| {
+                    let q = q.val.as_ref().unwrap();
+                    (q.top == p.top && q.height == p.height && (q.right() == p.left || p.right() == q.left)) ||
+                        (q.left == p.left && q.width == p.width && (q.bottom() == p.top || p.bottom() == q.top))
+                })
+            };
+            match merged {
+                None => break,
+                Some(i) => { r.union(self.rects.swap_remove(i)); }
+            }
+        }
+        self.rects.push(r);
+    }
 }
 
 pub struct WindowData {
     bounds: Rect,
     content: Vec<Vec<Texel>>,
-    invalid: Rect,
+    invalid: Region,
     parent: Option<Option<Arc<Mutex<WindowData>>>>,
     subwindows: Vec<Arc<Mutex<WindowData>>>,
+    cursor: Option<(isize, isize)>,
+    cursor_shape: CursorShape,
 }
 
 impl WindowData {
@@ -149,9 +253,11 @@ impl WindowData {
         WindowData {
             bounds: Rect::empty(),
             content: Vec::new(),
-            invalid: Rect::empty(),
+            invalid: Region::empty(),
             parent: None,
-            subwindows: Vec::new()
+            subwindows: Vec::new(),
+            cursor: None,
+            cursor_shape: CursorShape::Default
         }
     }
     fn is_detached(&self) -> bool {
@@ -164,47 +270,122 @@ impl WindowData {
     fn set_bounds(&mut self, bounds: Rect) -> Rect {
         let (height, width) = bounds.size();
         for row in &mut self.content {
-            row.resize(width as usize, Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None });
+            row.resize(width as usize, Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None });
+        }
+        self.content.resize(height as usize, vec![Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }; width as usize]);
+        for row in &mut self.content {
+            if let Some(last) = row.last_mut() {
+                if last.width() > 1 { *last = last.narrow(); }
+            }
         }
-        self.content.resize(height as usize, vec![Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }; width as usize]);
         self.invalid = self.invalid.inters_rect(&Rect::tlhw(0, 0, height, width));
         replace(&mut self.bounds, bounds)
     }
     fn out(&mut self, y: isize, x: isize, c: Texel) {
+        let c = if x + c.width() > self.bounds.size().1 { c.narrow() } else { c };
+        self.split_wide(y, x);
+        if c.width() > 1 {
+            self.split_wide(y, x + 1);
+            self.invalid.include(y, x + 1);
+            replace(&mut self.content[y as usize][x as usize + 1], c.tail());
+        }
         self.invalid.include(y, x);
         replace(&mut self.content[y as usize][x as usize], c);
     }
-    fn scr(&mut self, s: &mut Scr, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize, global_invalid: &mut Rect) -> Rect {
-        let mut invalid = replace(&mut self.invalid, Rect::empty());
+    fn split_wide(&mut self, y: isize, x: isize) {
+        let other = {
+            let row = &self.content[y as usize];
+            let t = &row[x as usize];
+            if t.is_tail() { x - 1 } else if t.width() > 1 { x + 1 } else { return; }
+        };
+        if other < 0 || other >= self.content[y as usize].len() as isize { return; }
+        let t = Texel { ch: ' '.into(), ..self.content[y as usize][other as usize].clone() };
+        self.invalid.include(y, other);
+        replace(&mut self.content[y as usize][other as usize], t);
+    }
+    fn viewport(&self, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize) -> Rect {
+        let mut bounds = self.bounds.clone();
+        bounds.offset(parent_y, parent_x);
+        bounds.inters_rect(&Rect::tlhw(parent_y, parent_x, crop_height, crop_width))
+    }
+    fn global_cursor(&self, parent_y: isize, parent_x: isize, viewport: &Rect, occluded: &Region) -> Option<(isize, isize)> {
+        let (y, x) = self.bounds.loc()?;
+        let (cursor_y, cursor_x) = self.cursor?;
+        let (y, x) = (parent_y + y + cursor_y, parent_x + x + cursor_x);
+        if !viewport.contains(y, x) || occluded.contains(y, x) { return None; }
+        Some((y, x))
+    }
+    fn scr(&mut self, s: &mut Scr, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize, occluded: &Region, global_invalid: &mut Region) -> Result<Rect, Error> {
+        let mut invalid = replace(&mut self.invalid, Region::empty());
         match self.bounds.loc() {
-            None => Rect::empty(),
+            None => Ok(Rect::empty()),
             Some((y, x)) => {
-                let mut bounds = self.bounds.clone();
-                bounds.offset(parent_y, parent_x);
-                let viewport = bounds.inters_rect(&Rect::tlhw(parent_y, parent_x, crop_height, crop_width));
+                let viewport = self.viewport(parent_y, parent_x, crop_height, crop_width);
+                let mut visible = Region::from(viewport.clone());
+                visible.subtract(occluded);
                 let y0 = parent_y + y;
                 let x0 = parent_x + x;
                 invalid.offset(y0, x0);
-                global_invalid.union(invalid.inters_rect(&viewport));
-                let err = viewport.inters_rect(global_invalid).scan(|yi, xi| {
-                    let texel = &self.content[(yi - y0) as usize][(xi - x0) as usize];
-                    match s.out(yi, xi, texel) {
-                        Err(()) => Some(()),
-                        Ok(()) => None
+                global_invalid.union(&invalid.inters(&visible));
+                let region = global_invalid.inters(&visible);
+                for rect in region.rects() {
+                    let (top, left) = rect.loc().unwrap();
+                    let (height, width) = rect.size();
+                    for yi in top .. top + height {
+                        let row = &self.content[(yi - y0) as usize];
+                        let mut run = Run { y: yi, x: left, texels: Vec::new() };
+                        for xi in left .. left + width {
+                            let texel = &row[(xi - x0) as usize];
+                            if texel.is_tail() {
+                                if !visible.contains(yi, xi - 1) {
+                                    run.push(s, xi, Texel { ch: ' '.into(), ..texel.clone() })?;
+                                    continue;
+                                }
+                                if !region.contains(yi, xi - 1) {
+                                    run.push(s, xi - 1, row[(xi - x0 - 1) as usize].clone())?;
+                                }
+                                run.push(s, xi, texel.clone())?;
+                            } else if texel.width() > 1 && !visible.contains(yi, xi + 1) {
+                                run.push(s, xi, texel.narrow())?;
+                            } else {
+                                run.push(s, xi, texel.clone())?;
+                            }
+                        }
+                        run.flush(s)?;
                     }
-                });
-                if let Some(()) = err {
-                    #[cfg(test)]
-                    panic!("NTFL render error occuried!");
-                    #[cfg(not(test))]
-                    eprintln!("NTFL render error occuried!");
                 }
-                viewport
+                Ok(viewport)
             }
         }
     }
 }
 
+struct Run {
+    y: isize,
+    x: isize,
+    texels: Vec<Texel>,
+}
+
+impl Run {
+    fn push(&mut self, s: &mut Scr, x: isize, t: Texel) -> Result<(), Error> {
+        let joins = self.x + self.texels.len() as isize == x && self.texels.first().map_or(false, |first| {
+            first.attr == t.attr && first.fg == t.fg && first.bg == t.bg
+        });
+        if !joins {
+            self.flush(s)?;
+            self.x = x;
+        }
+        self.texels.push(t);
+        Ok(())
+    }
+    fn flush(&mut self, s: &mut Scr) -> Result<(), Error> {
+        if self.texels.is_empty() { return Ok(()); }
+        s.out_run(self.y, self.x, &self.texels)?;
+        self.texels.clear();
+        Ok(())
+    }
+}
+
 pub struct Window {
     host: Arc<Mutex<WindowsHostValue>>,
     data: Arc<Mutex<WindowData>>,
@@ -212,7 +393,9 @@ pub struct Window {
 
 struct WindowsHostValue {
     windows: Vec<Arc<Mutex<WindowData>>>,
-    invalid: Rect,
+    invalid: Region,
+    invalid_all: bool,
+    focused: Option<Arc<Mutex<WindowData>>>,
 }
 
 pub struct WindowsHost {
@@ -221,29 +404,74 @@ pub struct WindowsHost {
 
 impl WindowsHost {
     pub fn new() -> WindowsHost {
-        WindowsHost { val: Arc::new(Mutex::new(WindowsHostValue { windows: Vec::new(), invalid: Rect::empty() })) }
+        WindowsHost { val: Arc::new(Mutex::new(WindowsHostValue { windows: Vec::new(), invalid: Region::empty(), invalid_all: false, focused: None })) }
     }
     pub fn new_window(&mut self) -> Window {
         Window { host: Arc::clone(&self.val), data: Arc::new(Mutex::new(WindowData::new())) }
     }
-    pub fn scr(&mut self, s: &mut Scr) {
-        fn scr_window(window: &mut WindowData, s: &mut Scr, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize, invalid: &mut Rect) {
-            let viewport = window.scr(s, parent_y, parent_x, crop_height, crop_width, invalid);
+    // Makes the next `scr` repaint the whole screen.
+    pub fn invalidate(&mut self) {
+        self.val.lock().unwrap().invalid_all = true;
+    }
+    // Returns where the focused window's cursor shows, having set its shape on the screen.
+    pub fn scr(&mut self, s: &mut Scr) -> Result<Option<(isize, isize)>, Error> {
+        fn viewports(window: &WindowData, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize, list: &mut Vec<Rect>) {
+            let viewport = window.viewport(parent_y, parent_x, crop_height, crop_width);
+            list.push(viewport.clone());
             if let Some((y, x)) = viewport.loc() {
                 let (height, width) = viewport.size();
-                for subwindow in window.subwindows.iter_mut() {
-                    scr_window(subwindow.lock().unwrap().deref_mut(), s, y, x, height, width, invalid);
+                for subwindow in window.subwindows.iter() {
+                    viewports(&subwindow.lock().unwrap(), y, x, height, width, list);
                 }
             }
         }
+        fn scr_window(w: &Arc<Mutex<WindowData>>, s: &mut Scr, parent_y: isize, parent_x: isize, crop_height: isize, crop_width: isize, occluded: &mut Iterator<Item=Region>, invalid: &mut Region, focused: Option<&Arc<Mutex<WindowData>>>, cursor: &mut Option<(isize, isize, CursorShape)>) -> Result<(), Error> {
+            let mut window = w.lock().unwrap();
+            let window_occluded = occluded.next().unwrap();
+            let viewport = window.scr(s, parent_y, parent_x, crop_height, crop_width, &window_occluded, invalid)?;
+            if focused.map_or(false, |f| Arc::ptr_eq(f, w)) {
+                *cursor = window.global_cursor(parent_y, parent_x, &viewport, &window_occluded).map(|(y, x)| (y, x, window.cursor_shape));
+            }
+            if let Some((y, x)) = viewport.loc() {
+                let (height, width) = viewport.size();
+                for subwindow in window.subwindows.iter() {
+                    scr_window(subwindow, s, y, x, height, width, occluded, invalid, focused, cursor)?;
+                }
+            }
+            Ok(())
+        }
         let mut ref_mut = self.val.lock().unwrap();
         let ref mut b = ref_mut.deref_mut();
-        let mut invalid = replace(&mut b.invalid, Rect::empty());
-        let height = s.get_height().unwrap();
-        let width = s.get_width().unwrap();
-        for w in b.windows.iter_mut() {
-            scr_window(&mut w.lock().unwrap(), s, 0, 0, height, width, &mut invalid);
+        let mut invalid = replace(&mut b.invalid, Region::empty());
+        let height = s.get_height()?;
+        let width = s.get_width()?;
+        if replace(&mut b.invalid_all, false) {
+            invalid.union_rect(Rect::tlhw(0, 0, height, width));
+        }
+        let mut list = Vec::new();
+        for w in b.windows.iter() {
+            viewports(&w.lock().unwrap(), 0, 0, height, width, &mut list);
         }
+        // Everything drawn later (windows above and subwindows) covers what is drawn earlier.
+        let mut above = Region::empty();
+        let mut occluded: Vec<Region> = list.into_iter().rev().map(|viewport| {
+            let o = above.clone();
+            above.union_rect(viewport);
+            o
+        }).collect();
+        occluded.reverse();
+        let mut occluded = occluded.into_iter();
+        let mut cursor = None;
+        for w in b.windows.iter() {
+            scr_window(w, s, 0, 0, height, width, &mut occluded, &mut invalid, b.focused.as_ref(), &mut cursor)?;
+        }
+        Ok(match cursor {
+            None => None,
+            Some((y, x, shape)) => {
+                s.set_cursor_shape(shape)?;
+                Some((y, x))
+            }
+        })
     }
 }
 
@@ -251,6 +479,19 @@ impl Window {
     pub fn out(&mut self, y: isize, x: isize, c: Texel) {
         self.data.lock().unwrap().out(y, x, c);
     }
+    // The cursor position is local to the window; it shows only while the window is focused.
+    pub fn set_cursor(&mut self, cursor: Option<(isize, isize)>) {
+        self.data.lock().unwrap().cursor = cursor;
+    }
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.data.lock().unwrap().cursor_shape = shape;
+    }
+    pub fn focus(&mut self) {
+        self.host.lock().unwrap().focused = Some(Arc::clone(&self.data));
+    }
+    pub fn is_focused(&self) -> bool {
+        self.host.lock().unwrap().focused.as_ref().map_or(false, |f| Arc::ptr_eq(f, &self.data))
+    }
     pub fn bounds(&self) -> MutexGuardRef<WindowData, Rect> {
         MutexGuardRef::new(self.data.lock().unwrap()).map(|data| &data.bounds)
     }
@@ -269,8 +510,8 @@ impl Window {
         if let Some((parent_y, parent_x)) = self.data.lock().unwrap().parent.as_ref().unwrap().as_ref().map_or(Some((0, 0)), |parent| global(&parent.lock().unwrap(), 0, 0)) {
             old_bounds.offset(parent_y, parent_x);
             new_bounds.offset(parent_y, parent_x);
-            self.host.lock().unwrap().invalid.union(old_bounds);
-            self.host.lock().unwrap().invalid.union(new_bounds);
+            self.host.lock().unwrap().invalid.union_rect(old_bounds);
+            self.host.lock().unwrap().invalid.union_rect(new_bounds);
         }
     }
     pub fn attach(&mut self) {
@@ -332,7 +573,7 @@ impl Window {
         let mut bounds = self.data.lock().unwrap().bounds.clone();
         if let Some((parent_y, parent_x)) = self.data.lock().unwrap().parent.as_ref().unwrap().as_ref().map_or(Some((0, 0)), |parent| global(&parent.lock().unwrap(), 0, 0)) {
             bounds.offset(parent_y, parent_x);
-            self.host.lock().unwrap().invalid.union(bounds);
+            self.host.lock().unwrap().invalid.union_rect(bounds);
         }
         if let Some(ref parent) = self.data.lock().unwrap().parent.as_ref().unwrap() {
             set_index(&mut parent.lock().unwrap().subwindows, &self.data, index)
@@ -345,6 +586,10 @@ impl Window {
 impl Drop for Window {
     fn drop(&mut self) {
         self.detach_core();
+        let mut host = self.host.lock().unwrap();
+        if host.focused.as_ref().map_or(false, |f| Arc::ptr_eq(f, &self.data)) {
+            host.focused = None;
+        }
     }
 }
 
@@ -352,6 +597,7 @@ impl Drop for Window {
 mod tests {
     use std::sync::Arc;
     use window::Rect;
+    use window::Region;
     use window::Window;
     use window::WindowData;
     use window::WindowsHost;
@@ -360,6 +606,7 @@ mod tests {
     use scr::Color;
     use scr::Attr;
     use scr::Texel;
+    use scr::CursorShape;
 
     #[test]
     fn rect_contains() {
@@ -369,22 +616,169 @@ mod tests {
         assert!(!r.contains(10, 5));
     }
 
+    #[test]
+    fn rect_include() {
+        let mut r = Rect::tlhw(5, 7, 2, 3);
+        r.include(3, 4);
+        assert_eq!(Rect::tlhw(3, 4, 4, 6), r);
+        r.include(8, 10);
+        assert_eq!(Rect::tlhw(3, 4, 6, 7), r);
+        r.include(5, 5);
+        assert_eq!(Rect::tlhw(3, 4, 6, 7), r);
+    }
+
+    #[test]
+    fn region_ops() {
+        let mut r = Region::empty();
+        r.union_rect(Rect::tlhw(0, 0, 1, 80));
+        r.union_rect(Rect::tlhw(23, 0, 1, 80));
+        assert_eq!(2, r.rects().len());
+        assert!(!r.contains(10, 10));
+        assert_eq!(Rect::tlhw(0, 0, 24, 80), r.bounds());
+        r.union_rect(Rect::tlhw(0, 40, 24, 10));
+        assert!(r.contains(10, 45));
+        assert!(!r.contains(10, 39));
+        assert_eq!(80 + 80 + 22 * 10, r.rects().iter().map(|r| r.size().0 * r.size().1).sum::<isize>());
+        r.subtract_rect(&Rect::tlhw(0, 0, 24, 45));
+        assert!(!r.contains(0, 44));
+        assert!(r.contains(0, 45));
+        let i = r.inters_rect(&Rect::tlhw(20, 0, 10, 100));
+        assert_eq!(Rect::tlhw(20, 45, 4, 35), i.bounds());
+        assert!(!i.contains(21, 60));
+        let mut line = Region::empty();
+        for x in 0 .. 5 {
+            line.include(2, x);
+        }
+        assert_eq!(Region::from(Rect::tlhw(2, 0, 1, 5)), line);
+        assert_eq!(Region::empty(), line.inters(&r));
+    }
+
+    #[test]
+    fn windows_host_scr_damage() {
+        let mut host = WindowsHost::new();
+        let mut w = host.new_window();
+        w.attach();
+        w.set_bounds(Rect::tlhw(0, 0, 10, 10));
+        let mut s = TestScr::new(10, 10);
+        host.scr(&mut s).unwrap();
+        s.runs.clear();
+        let t = Texel { ch: '-'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None };
+        w.out(0, 0, t.clone());
+        w.out(9, 9, t.clone());
+        host.scr(&mut s).unwrap();
+        assert_eq!(vec![(0, 0, 1), (9, 9, 1)], s.runs);
+    }
+
+    #[test]
+    fn windows_host_scr_occlusion() {
+        let mut host = WindowsHost::new();
+        let mut lower = host.new_window();
+        lower.attach();
+        lower.set_bounds(Rect::tlhw(0, 0, 1, 10));
+        let mut upper = host.new_window();
+        upper.attach();
+        upper.set_bounds(Rect::tlhw(0, 3, 1, 4));
+        let a = Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None };
+        let b = Texel { ch: 'b'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None };
+        for x in 0 .. 10 {
+            lower.out(0, x, a.clone());
+        }
+        for x in 0 .. 4 {
+            upper.out(0, x, b.clone());
+        }
+        let mut s = TestScr::new(1, 10);
+        host.scr(&mut s).unwrap();
+        assert_eq!(vec![(0, 0, 3), (0, 7, 3), (0, 3, 4)], s.runs);
+        s.runs.clear();
+        lower.out(0, 5, b.clone());
+        host.scr(&mut s).unwrap();
+        assert!(s.runs.is_empty());
+        assert_eq!(b, *s.content(0, 5));
+    }
+
+    #[test]
+    fn windows_host_invalidate() {
+        let mut host = WindowsHost::new();
+        let mut w = host.new_window();
+        w.attach();
+        w.set_bounds(Rect::tlhw(1, 0, 1, 3));
+        let mut s = TestScr::new(3, 3);
+        host.scr(&mut s).unwrap();
+        s.runs.clear();
+        host.scr(&mut s).unwrap();
+        assert!(s.runs.is_empty());
+        host.invalidate();
+        host.scr(&mut s).unwrap();
+        assert_eq!(vec![(1, 0, 3)], s.runs);
+    }
+
+    #[test]
+    fn windows_host_cursor() {
+        let mut host = WindowsHost::new();
+        let mut w = host.new_window();
+        w.attach();
+        w.set_bounds(Rect::tlhw(1, 2, 2, 4));
+        w.set_cursor(Some((1, 3)));
+        w.set_cursor_shape(CursorShape::Bar);
+        let mut s = TestScr::new(5, 10);
+        assert_eq!(Ok(None), host.scr(&mut s));
+        w.focus();
+        assert!(w.is_focused());
+        assert_eq!(Ok(Some((2, 5))), host.scr(&mut s));
+        assert_eq!(CursorShape::Bar, s.cursor_shape);
+        let mut upper = host.new_window();
+        upper.attach();
+        upper.set_bounds(Rect::tlhw(2, 5, 1, 1));
+        assert_eq!(Ok(None), host.scr(&mut s));
+        upper.detach();
+        w.set_cursor(Some((1, 4)));
+        assert_eq!(Ok(None), host.scr(&mut s));
+        w.set_cursor(Some((0, 0)));
+        assert_eq!(Ok(Some((1, 2))), host.scr(&mut s));
+        let mut sub = host.new_window();
+        sub.attach_to(&mut w);
+        sub.set_bounds(Rect::tlhw(1, 1, 1, 2));
+        sub.set_cursor(Some((0, 1)));
+        sub.focus();
+        assert!(!w.is_focused());
+        assert_eq!(Ok(Some((2, 4))), host.scr(&mut s));
+        drop(sub);
+        assert_eq!(Ok(None), host.scr(&mut s));
+    }
+
     #[test]
     fn window_scr() {
         let mut w = WindowData::new();
         w.set_bounds(Rect::tlhw(3, 5, 1, 2));
-        assert_eq!(Rect::empty(), w.invalid);
-        w.out(0, 0, Texel { ch: '+', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        w.out(0, 1, Texel { ch: '-', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        assert_eq!(Region::empty(), w.invalid);
+        w.out(0, 0, Texel { ch: '+'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        w.out(0, 1, Texel { ch: '-'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
         let mut s = TestScr::new(100, 100);
-        let mut invalid = Rect::empty();
-        w.scr(&mut s, 0, 0, 100, 100, &mut invalid);
-        assert!(Some((3, 5)) == invalid.loc());
-        assert!((1, 2) == invalid.size(), format!("({}, {})", invalid.size().0, invalid.size().1));
+        let mut invalid = Region::empty();
+        w.scr(&mut s, 0, 0, 100, 100, &Region::empty(), &mut invalid).unwrap();
+        assert_eq!(Region::from(Rect::tlhw(3, 5, 1, 2)), invalid);
         assert!('+' == s.content(3, 5).ch, format!("{}", s.content(3, 5).ch));
         assert!('-' == s.content(3, 6).ch, format!("{}", s.content(3, 6).ch));
     }
 
+    #[test]
+    fn window_scr_runs() {
+        let mut w = WindowData::new();
+        w.set_bounds(Rect::tlhw(1, 2, 2, 4));
+        let plain = Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None };
+        let bold = Texel { attr: Attr::BOLD, ..plain.clone() };
+        for y in 0 .. 2 {
+            for x in 0 .. 4 {
+                w.out(y, x, if y == 1 && x == 2 { bold.clone() } else { plain.clone() });
+            }
+        }
+        let mut s = TestScr::new(10, 10);
+        let mut invalid = Region::empty();
+        w.scr(&mut s, 0, 0, 10, 10, &Region::empty(), &mut invalid).unwrap();
+        assert_eq!(vec![(1, 2, 4), (2, 2, 2), (2, 4, 1), (2, 5, 1)], s.runs);
+        assert_eq!(bold, *s.content(2, 4));
+    }
+
     #[test]
     fn window_set_bounds() {
         let mut w = WindowData::new();
@@ -413,7 +807,7 @@ mod tests {
         let mut window = host.new_window();
         window.attach();
         window.set_bounds(Rect::tlhw(3, 5, 1, 2));
-        host.scr(&mut s);
+        host.scr(&mut s).unwrap();
     }
 
     #[test]
@@ -423,9 +817,9 @@ mod tests {
         let mut window = host.new_window();
         window.attach();
         window.set_bounds(Rect::tlhw(3, 5, 1, 2));
-        window.out(0, 0, Texel { ch: '+', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        window.out(0, 1, Texel { ch: '-', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        host.scr(&mut s);
+        window.out(0, 0, Texel { ch: '+'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        window.out(0, 1, Texel { ch: '-'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        host.scr(&mut s).unwrap();
         assert!('+' == s.content(3, 5).ch, format!("{}", s.content(3, 5).ch));
         assert!('-' == s.content(3, 6).ch, format!("{}", s.content(3, 6).ch));
     }
@@ -438,7 +832,7 @@ mod tests {
         let mut sub = host.new_window();
         sub.attach_to(&mut window);
         sub.set_bounds(Rect::tlhw(1, 1, 1, 1));
-        assert_eq!(Rect::empty(), host.val.lock().unwrap().invalid);
+        assert_eq!(Region::empty(), host.val.lock().unwrap().invalid);
     }
 
     #[test]
@@ -448,9 +842,9 @@ mod tests {
         let mut window = host.new_window();
         window.attach();
         window.set_bounds(Rect::tlhw(-1, -5, 1, 2));
-        window.out(0, 0, Texel { ch: '+', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        window.out(0, 1, Texel { ch: '-', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        host.scr(&mut s);
+        window.out(0, 0, Texel { ch: '+'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        window.out(0, 1, Texel { ch: '-'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        host.scr(&mut s).unwrap();
     }
 
     #[test]
@@ -464,31 +858,31 @@ mod tests {
             let mut sub = host.new_window();
             sub.attach_to(&mut window);
             sub.set_bounds(Rect::tlhw(10, 20, 10, 15));
-            host.scr(&mut s);
-            assert_eq!(Rect::empty(), sub.data.lock().unwrap().invalid);
-            sub.out(0, 0, Texel { ch: '+', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-            assert_eq!(Rect::tlhw(0, 0, 1, 1), sub.data.lock().unwrap().invalid);
+            host.scr(&mut s).unwrap();
+            assert_eq!(Region::empty(), sub.data.lock().unwrap().invalid);
+            sub.out(0, 0, Texel { ch: '+'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+            assert_eq!(Region::from(Rect::tlhw(0, 0, 1, 1)), sub.data.lock().unwrap().invalid);
             sub.set_bounds(Rect::tlhw(10, 20, 9, 14));
-            assert_eq!(Rect::tlhw(0, 0, 1, 1), sub.data.lock().unwrap().invalid);
-            assert_eq!(Rect::tlhw(0, 0, 10, 15), host.val.lock().unwrap().invalid);
-            host.scr(&mut s);
-            assert_eq!(Rect::empty(), host.val.lock().unwrap().invalid);
+            assert_eq!(Region::from(Rect::tlhw(0, 0, 1, 1)), sub.data.lock().unwrap().invalid);
+            assert_eq!(Region::from(Rect::tlhw(0, 0, 10, 15)), host.val.lock().unwrap().invalid);
+            host.scr(&mut s).unwrap();
+            assert_eq!(Region::empty(), host.val.lock().unwrap().invalid);
         }
-        assert_eq!(Rect::tlhw(0, 0, 9, 14), host.val.lock().unwrap().invalid);
+        assert_eq!(Region::from(Rect::tlhw(0, 0, 9, 14)), host.val.lock().unwrap().invalid);
     }
 
     #[test]
     fn window_z_index() {
         fn fill3x3(window: &mut Window, fg: Color) {
-            window.out(0, 0, Texel { ch: '1', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(0, 1, Texel { ch: '2', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(0, 2, Texel { ch: '3', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(1, 0, Texel { ch: '4', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(1, 1, Texel { ch: '5', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(1, 2, Texel { ch: '6', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(2, 0, Texel { ch: '7', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(2, 1, Texel { ch: '8', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
-            window.out(2, 2, Texel { ch: '9', attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(0, 0, Texel { ch: '1'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(0, 1, Texel { ch: '2'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(0, 2, Texel { ch: '3'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(1, 0, Texel { ch: '4'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(1, 1, Texel { ch: '5'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(1, 2, Texel { ch: '6'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(2, 0, Texel { ch: '7'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(2, 1, Texel { ch: '8'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
+            window.out(2, 2, Texel { ch: '9'.into(), attr: Attr::NORMAL, fg: fg, bg: Some(Color::Black) });
         }
         let mut scr = TestScr::new(4, 4);
         let mut host = WindowsHost::new();
@@ -500,48 +894,48 @@ mod tests {
         window2.attach();
         window2.set_bounds(Rect::tlhw(1, 1, 3, 3));
         fill3x3(&mut window2, Color::Red);
-        host.scr(&mut scr);
+        host.scr(&mut scr).unwrap();
         assert_eq!([
-            Texel { ch: '1', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '2', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '3', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: 'T', attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
-            Texel { ch: '4', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '1', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '2', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '3', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '7', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '4', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '5', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '6', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: 'T', attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
-            Texel { ch: '7', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '8', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '9', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '1'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '2'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '3'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: 'T'.into(), attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
+            Texel { ch: '4'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '1'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '2'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '3'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '7'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '4'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '5'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '6'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: 'T'.into(), attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
+            Texel { ch: '7'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '8'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '9'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
         ], &*scr.content);
         assert_eq!(0, window1.z_index());
         assert_eq!(1, window2.z_index());
         window1.set_z_index(5);
         assert_eq!(0, window2.z_index());
         assert_eq!(1, window1.z_index());
-        host.scr(&mut scr);
+        host.scr(&mut scr).unwrap();
         assert_eq!([
-            Texel { ch: '1', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '2', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '3', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: 'T', attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
-            Texel { ch: '4', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '5', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '6', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '3', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '7', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '8', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '9', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
-            Texel { ch: '6', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: 'T', attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
-            Texel { ch: '7', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '8', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
-            Texel { ch: '9', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '1'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '2'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '3'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: 'T'.into(), attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
+            Texel { ch: '4'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '5'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '6'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '3'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '7'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '8'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '9'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) },
+            Texel { ch: '6'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: 'T'.into(), attr: Attr::NORMAL, fg: Color::Cyan, bg: Some(Color::Red) },
+            Texel { ch: '7'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '8'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
+            Texel { ch: '9'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) },
         ], &*scr.content);
     }
 
@@ -552,14 +946,14 @@ mod tests {
         let mut window = host.new_window();
         window.attach();
         window.set_bounds(Rect::tlhw(0, 0, 10, 136));
-        window.out(6, 133, Texel { ch: 'A', attr: Attr::NORMAL, fg: Color::Green, bg: None });
-        window.out(6, 134, Texel { ch: 'B', attr: Attr::NORMAL, fg: Color::Green, bg: None });
-        window.out(6, 135, Texel { ch: 'c', attr: Attr::NORMAL, fg: Color::Green, bg: None });
-        window.out(5, 5, Texel { ch: 'l', attr: Attr::ALTCHARSET | Attr::REVERSE, fg: Color::Green, bg: Some(Color::Black) });
-        host.scr(&mut scr);
-        window.out(6, 2, Texel { ch: 'i', attr: Attr::UNDERLINE, fg: Color::Red, bg: None });
-        host.scr(&mut scr);
-        assert_eq!(Texel { ch: 'i', attr: Attr::UNDERLINE, fg: Color::Red, bg: None }, scr.content[6 * 136 + 2]);
+        window.out(6, 133, Texel { ch: 'A'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None });
+        window.out(6, 134, Texel { ch: 'B'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None });
+        window.out(6, 135, Texel { ch: 'c'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: None });
+        window.out(5, 5, Texel { ch: 'l'.into(), attr: Attr::ALTCHARSET | Attr::REVERSE, fg: Color::Green, bg: Some(Color::Black) });
+        host.scr(&mut scr).unwrap();
+        window.out(6, 2, Texel { ch: 'i'.into(), attr: Attr::UNDERLINE, fg: Color::Red, bg: None });
+        host.scr(&mut scr).unwrap();
+        assert_eq!(Texel { ch: 'i'.into(), attr: Attr::UNDERLINE, fg: Color::Red, bg: None }, scr.content[6 * 136 + 2]);
     }
 
     #[test]
@@ -581,47 +975,47 @@ mod tests {
         let mut sub3 = host.new_window();
         sub3.attach_to(&mut window2);
         sub3.set_bounds(Rect::tlhw(0, 1, 3, 2));
-        sub3.out(0, 0, Texel { ch: 'y', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Blue) });
+        sub3.out(0, 0, Texel { ch: 'y'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Blue) });
         let mut subsub = host.new_window();
         subsub.attach_to(&mut sub2);
         subsub.set_bounds(Rect::tlhw(1, 1, 1, 1));
-        window1.out(0, 0, Texel { ch: 'a', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) });
-        window1.out(0, 1, Texel { ch: 'b', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) });
-        sub2.out(0, 0, Texel { ch: 'D', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
-        host.scr(&mut scr);
-        assert_eq!(Texel { ch: 'a', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 0]);
-        assert_eq!(Texel { ch: 'b', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 1]);
-        assert_eq!(Texel { ch: 'D', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) }, scr.content[0 * 4 + 2]);
-        assert_eq!(Texel { ch: 'y', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Blue) }, scr.content[0 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 3]);
+        window1.out(0, 0, Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) });
+        window1.out(0, 1, Texel { ch: 'b'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) });
+        sub2.out(0, 0, Texel { ch: 'D'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) });
+        host.scr(&mut scr).unwrap();
+        assert_eq!(Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 0]);
+        assert_eq!(Texel { ch: 'b'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 1]);
+        assert_eq!(Texel { ch: 'D'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) }, scr.content[0 * 4 + 2]);
+        assert_eq!(Texel { ch: 'y'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Blue) }, scr.content[0 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 3]);
         sub3.detach();
-        host.scr(&mut scr);
-        assert_eq!(Texel { ch: 'a', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 0]);
-        assert_eq!(Texel { ch: 'b', attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 1]);
-        assert_eq!(Texel { ch: 'D', attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) }, scr.content[0 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[0 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 3]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 0]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 1]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 2]);
-        assert_eq!(Texel { ch: 'X', attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 3]);
+        host.scr(&mut scr).unwrap();
+        assert_eq!(Texel { ch: 'a'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 0]);
+        assert_eq!(Texel { ch: 'b'.into(), attr: Attr::NORMAL, fg: Color::Red, bg: Some(Color::Black) }, scr.content[0 * 4 + 1]);
+        assert_eq!(Texel { ch: 'D'.into(), attr: Attr::NORMAL, fg: Color::Green, bg: Some(Color::Black) }, scr.content[0 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[0 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[1 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[2 * 4 + 3]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 0]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 1]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 2]);
+        assert_eq!(Texel { ch: 'X'.into(), attr: Attr::BOLD, fg: Color::Red, bg: None }, scr.content[3 * 4 + 3]);
     }
 }